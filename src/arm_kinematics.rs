@@ -0,0 +1,389 @@
+//! Planar 2-link arm kinematics in the r-h plane of the arm plot.
+//!
+//! Angles are in radians. The shoulder angle is measured from horizontal, the
+//! elbow angle is the bend of the forearm relative to the upper arm, positive
+//! when the forearm folds downwards (which is how the arm is usually driven).
+
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ElbowConfig {
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ArmGeometry {
+    pub upper_arm: f64,
+    pub forearm: f64,
+    /// Height of the shoulder pivot above the plot origin.
+    pub base_height: f64,
+    /// Added to the kinematic joint angles to get servo angles.
+    pub shoulder_offset: f64,
+    pub elbow_offset: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct JointAngles {
+    pub shoulder: f64,
+    pub elbow: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Unreachable {
+    TooFar,
+    TooClose,
+}
+
+impl ArmGeometry {
+    pub fn new() -> Self {
+        Self {
+            upper_arm: 8.0,
+            forearm: 8.0,
+            base_height: 7.0,
+            shoulder_offset: 0.0,
+            elbow_offset: 0.0,
+        }
+    }
+
+    pub fn shoulder(&self) -> [f64; 2] {
+        [0.0, self.base_height]
+    }
+
+    pub fn max_reach(&self) -> f64 {
+        self.upper_arm + self.forearm
+    }
+
+    pub fn min_reach(&self) -> f64 {
+        (self.upper_arm - self.forearm).abs()
+    }
+
+    /// Joint angles that put the claw at `(r, h)`.
+    pub fn inverse(&self, r: f64, h: f64, config: ElbowConfig) -> Result<JointAngles, Unreachable> {
+        let dy = h - self.base_height;
+        let distance_sq = r * r + dy * dy;
+        let distance = distance_sq.sqrt();
+
+        if distance > self.max_reach() {
+            return Err(Unreachable::TooFar);
+        }
+        if distance < self.min_reach() {
+            return Err(Unreachable::TooClose);
+        }
+
+        let cos_elbow = ((distance_sq - self.upper_arm.powi(2) - self.forearm.powi(2))
+            / (2.0 * self.upper_arm * self.forearm))
+            .clamp(-1.0, 1.0);
+        let elbow = match config {
+            ElbowConfig::Up => cos_elbow.acos(),
+            ElbowConfig::Down => -cos_elbow.acos(),
+        };
+        let shoulder = dy.atan2(r)
+            + (self.forearm * elbow.sin()).atan2(self.upper_arm + self.forearm * elbow.cos());
+
+        Ok(JointAngles {
+            shoulder: shoulder + self.shoulder_offset,
+            elbow: elbow + self.elbow_offset,
+        })
+    }
+
+    /// Shoulder, elbow and claw positions for the given joint angles.
+    pub fn forward(&self, angles: JointAngles) -> [[f64; 2]; 3] {
+        let shoulder = angles.shoulder - self.shoulder_offset;
+        let forearm = shoulder - (angles.elbow - self.elbow_offset);
+
+        let base = self.shoulder();
+        let elbow = [
+            base[0] + self.upper_arm * shoulder.cos(),
+            base[1] + self.upper_arm * shoulder.sin(),
+        ];
+        let claw = [
+            elbow[0] + self.forearm * forearm.cos(),
+            elbow[1] + self.forearm * forearm.sin(),
+        ];
+
+        [base, elbow, claw]
+    }
+
+    /// Linkage to draw for a claw target, if it can be reached.
+    pub fn linkage(&self, r: f64, h: f64, config: ElbowConfig) -> Option<Vec<[f64; 2]>> {
+        self.inverse(r, h, config)
+            .ok()
+            .map(|angles| self.forward(angles).to_vec())
+    }
+}
+
+/// Why a claw target was rejected.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Violation {
+    Unreachable(Unreachable),
+    ShoulderLimit,
+    ElbowLimit,
+    KeepOut,
+}
+
+/// Axis-aligned region in the r-h plane the linkage must stay out of.
+#[derive(Clone, PartialEq, Debug)]
+pub struct KeepOut {
+    pub name: String,
+    pub min: [f64; 2],
+    pub max: [f64; 2],
+}
+
+impl KeepOut {
+    pub fn contains(&self, p: [f64; 2]) -> bool {
+        p[0] > self.min[0] && p[0] < self.max[0] && p[1] > self.min[1] && p[1] < self.max[1]
+    }
+
+    pub fn outline(&self) -> Vec<[f64; 2]> {
+        vec![
+            [self.min[0], self.min[1]],
+            [self.max[0], self.min[1]],
+            [self.max[0], self.max[1]],
+            [self.min[0], self.max[1]],
+        ]
+    }
+}
+
+/// Joint limits (in servo angles, i.e. including the geometry offsets) and
+/// keep-out zones.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ArmLimits {
+    pub shoulder: [f64; 2],
+    pub elbow: [f64; 2],
+    pub keep_out: Vec<KeepOut>,
+}
+
+/// Points sampled along each link when checking keep-out zones.
+const LINK_SAMPLES: usize = 8;
+
+impl ArmLimits {
+    pub fn new() -> Self {
+        Self {
+            shoulder: [-0.5 * PI, PI],
+            elbow: [-PI, PI],
+            keep_out: vec![
+                KeepOut {
+                    name: String::from("Floor"),
+                    min: [-50.0, -50.0],
+                    max: [50.0, 0.0],
+                },
+                KeepOut {
+                    name: String::from("Chassis"),
+                    min: [-4.0, 0.0],
+                    max: [4.0, 5.0],
+                },
+            ],
+        }
+    }
+
+    pub fn check(
+        &self,
+        geometry: &ArmGeometry,
+        r: f64,
+        h: f64,
+        config: ElbowConfig,
+    ) -> Result<JointAngles, Violation> {
+        let angles = geometry
+            .inverse(r, h, config)
+            .map_err(Violation::Unreachable)?;
+
+        if angles.shoulder < self.shoulder[0] || angles.shoulder > self.shoulder[1] {
+            return Err(Violation::ShoulderLimit);
+        }
+        if angles.elbow < self.elbow[0] || angles.elbow > self.elbow[1] {
+            return Err(Violation::ElbowLimit);
+        }
+
+        let linkage = geometry.forward(angles);
+        for link in linkage.windows(2) {
+            for i in 1..=LINK_SAMPLES {
+                let t = i as f64 / LINK_SAMPLES as f64;
+                let p = [
+                    link[0][0] + t * (link[1][0] - link[0][0]),
+                    link[0][1] + t * (link[1][1] - link[0][1]),
+                ];
+                if self.keep_out.iter().any(|zone| zone.contains(p)) {
+                    return Err(Violation::KeepOut);
+                }
+            }
+        }
+
+        Ok(angles)
+    }
+
+    /// Valid claw positions on a grid over `[min, max]`, for shading.
+    pub fn workspace(
+        &self,
+        geometry: &ArmGeometry,
+        config: ElbowConfig,
+        min: [f64; 2],
+        max: [f64; 2],
+        step: f64,
+    ) -> Vec<[f64; 2]> {
+        let mut points = Vec::new();
+        let mut r = min[0];
+        while r <= max[0] {
+            let mut h = min[1];
+            while h <= max[1] {
+                if self.check(geometry, r, h, config).is_ok() {
+                    points.push([r, h]);
+                }
+                h += step;
+            }
+            r += step;
+        }
+        points
+    }
+
+    /// Closest valid point to `(r, h)`: the nearest point of `workspace`, a
+    /// grid from [`Self::workspace`] built once per geometry and limits, then
+    /// refined by bisecting towards the target.
+    pub fn clamp(
+        &self,
+        geometry: &ArmGeometry,
+        r: f64,
+        h: f64,
+        config: ElbowConfig,
+        workspace: &[[f64; 2]],
+    ) -> Option<[f64; 2]> {
+        if self.check(geometry, r, h, config).is_ok() {
+            return Some([r, h]);
+        }
+
+        let mut valid = workspace.iter().copied().min_by(|a, b| {
+            let da = (a[0] - r).hypot(a[1] - h);
+            let db = (b[0] - r).hypot(b[1] - h);
+            da.total_cmp(&db)
+        })?;
+
+        let mut invalid = [r, h];
+        for _ in 0..20 {
+            let mid = [0.5 * (valid[0] + invalid[0]), 0.5 * (valid[1] + invalid[1])];
+            if self.check(geometry, mid[0], mid[1], config).is_ok() {
+                valid = mid;
+            } else {
+                invalid = mid;
+            }
+        }
+
+        Some(valid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f64; 2], b: [f64; 2]) {
+        assert!(
+            (a[0] - b[0]).abs() < 1e-9 && (a[1] - b[1]).abs() < 1e-9,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn forward_inverts_inverse() {
+        let mut geometry = ArmGeometry::new();
+        geometry.forearm = 6.0;
+        geometry.shoulder_offset = 0.3;
+        geometry.elbow_offset = -0.2;
+
+        for config in [ElbowConfig::Up, ElbowConfig::Down] {
+            for (r, h) in [(10.0, 10.0), (3.0, 1.0), (-5.0, 12.0), (13.0, 7.0)] {
+                let angles = geometry.inverse(r, h, config).unwrap();
+                assert_close(geometry.forward(angles)[2], [r, h]);
+            }
+        }
+    }
+
+    #[test]
+    fn elbow_up_matches_original_formula() {
+        // The arm view used to compute this inline for 8/8 links at height 7.
+        let geometry = ArmGeometry::new();
+        let (r, h): (f64, f64) = (10.0, 10.0);
+        let k = (((h - 7.0).powf(2.0) + r.powf(2.0) - 128.0) / 128.0).acos();
+        let l = ((h - 7.0) / r).atan() + 0.5 * k;
+
+        let angles = geometry.inverse(r, h, ElbowConfig::Up).unwrap();
+        assert!((angles.shoulder - l).abs() < 1e-9);
+        assert!((angles.elbow - k).abs() < 1e-9);
+        assert_close(
+            geometry.forward(angles)[1],
+            [8.0 * l.cos(), 8.0 * l.sin() + 7.0],
+        );
+    }
+
+    #[test]
+    fn elbow_configs_mirror_about_target_line() {
+        let geometry = ArmGeometry::new();
+        let up = geometry.inverse(12.0, 7.0, ElbowConfig::Up).unwrap();
+        let down = geometry.inverse(12.0, 7.0, ElbowConfig::Down).unwrap();
+        assert!((up.shoulder + down.shoulder).abs() < 1e-9);
+        assert!(geometry.forward(up)[1][1] > 7.0);
+        assert!(geometry.forward(down)[1][1] < 7.0);
+    }
+
+    #[test]
+    fn unreachable_points_are_reported() {
+        let mut geometry = ArmGeometry::new();
+        assert_eq!(
+            geometry.inverse(20.0, 7.0, ElbowConfig::Up),
+            Err(Unreachable::TooFar)
+        );
+
+        geometry.forearm = 5.0;
+        assert_eq!(
+            geometry.inverse(1.0, 7.0, ElbowConfig::Up),
+            Err(Unreachable::TooClose)
+        );
+    }
+
+    #[test]
+    fn full_extension_is_reachable() {
+        let geometry = ArmGeometry::new();
+        let angles = geometry.inverse(16.0, 7.0, ElbowConfig::Up).unwrap();
+        assert!(angles.elbow.abs() < 1e-6);
+        assert_close(geometry.forward(angles)[2], [16.0, 7.0]);
+    }
+
+    #[test]
+    fn limits_reject_out_of_range_joints_and_keep_out() {
+        let geometry = ArmGeometry::new();
+        let mut limits = ArmLimits::new();
+        assert!(limits.check(&geometry, 10.0, 10.0, ElbowConfig::Up).is_ok());
+        assert_eq!(
+            limits.check(&geometry, 10.0, -1.0, ElbowConfig::Up),
+            Err(Violation::KeepOut)
+        );
+
+        limits.elbow = [0.0, 0.5];
+        assert_eq!(
+            limits.check(&geometry, 10.0, 10.0, ElbowConfig::Up),
+            Err(Violation::ElbowLimit)
+        );
+    }
+
+    #[test]
+    fn clamp_moves_unreachable_targets_onto_the_boundary() {
+        let geometry = ArmGeometry::new();
+        let limits = ArmLimits::new();
+        let workspace =
+            limits.workspace(&geometry, ElbowConfig::Up, [-1.0, -1.0], [18.0, 18.0], 0.5);
+
+        let clamped = limits
+            .clamp(&geometry, 30.0, 7.0, ElbowConfig::Up, &workspace)
+            .unwrap();
+        assert!(limits
+            .check(&geometry, clamped[0], clamped[1], ElbowConfig::Up)
+            .is_ok());
+        assert!((clamped[0] - 16.0).abs() < 0.5 && (clamped[1] - 7.0).abs() < 0.5);
+
+        assert_eq!(
+            limits.clamp(&geometry, 10.0, 10.0, ElbowConfig::Up, &workspace),
+            Some([10.0, 10.0])
+        );
+    }
+}
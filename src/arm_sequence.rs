@@ -0,0 +1,277 @@
+//! Taught arm keyframe sequences and their playback.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::Instant;
+
+use crate::arm_kinematics::{ArmGeometry, ArmLimits, ElbowConfig, JointAngles, Violation};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Keyframe {
+    pub name: String,
+    pub arm_r: f32,
+    pub arm_h: f32,
+    /// Absolute turntable angle in degrees.
+    pub turntable: f32,
+    pub claw_closed: bool,
+    /// Seconds to move here from the previous keyframe.
+    pub duration: f32,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Interpolation {
+    Joint,
+    Cartesian,
+}
+
+pub struct Playback {
+    /// Keyframe currently being moved towards.
+    pub target: usize,
+    pub paused: bool,
+    from: Keyframe,
+    progress: f32,
+    last_tick: Instant,
+}
+
+/// Where the arm should be on this frame of playback.
+pub struct PlaybackStep {
+    pub arm_r: f32,
+    pub arm_h: f32,
+    pub turntable: f32,
+    /// Set when a keyframe was reached and its claw state should be applied.
+    pub claw_closed: Option<bool>,
+}
+
+pub struct ArmSequence {
+    pub keyframes: Vec<Keyframe>,
+    pub interpolation: Interpolation,
+    pub playback: Option<Playback>,
+    pub path: String,
+    pub status: String,
+}
+
+impl ArmSequence {
+    pub fn new() -> Self {
+        Self {
+            keyframes: Vec::new(),
+            interpolation: Interpolation::Joint,
+            playback: None,
+            path: String::from("arm_sequence.json"),
+            status: String::new(),
+        }
+    }
+
+    /// Add a keyframe at the given pose, unless it breaks the limits.
+    pub fn record(
+        &mut self,
+        keyframe: Keyframe,
+        geometry: &ArmGeometry,
+        elbow: ElbowConfig,
+        limits: &ArmLimits,
+    ) {
+        if let Err(violation) = check(&keyframe, geometry, elbow, limits) {
+            self.status = format!("Not recorded, pose is invalid ({:?}).", violation);
+            return;
+        }
+        self.keyframes.push(Keyframe {
+            name: format!("Keyframe {}", self.keyframes.len()),
+            duration: 1.0,
+            ..keyframe
+        });
+    }
+
+    /// Index and problem of every keyframe that breaks the limits.
+    pub fn violations(
+        &self,
+        geometry: &ArmGeometry,
+        elbow: ElbowConfig,
+        limits: &ArmLimits,
+    ) -> Vec<(usize, Violation)> {
+        self.keyframes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, k)| check(k, geometry, elbow, limits).err().map(|v| (i, v)))
+            .collect()
+    }
+
+    /// Start playing from the arm's current pose, if every keyframe is valid.
+    pub fn play(
+        &mut self,
+        current: Keyframe,
+        geometry: &ArmGeometry,
+        elbow: ElbowConfig,
+        limits: &ArmLimits,
+    ) {
+        if self.keyframes.is_empty() {
+            return;
+        }
+        if let Some((i, violation)) = self.violations(geometry, elbow, limits).first() {
+            self.status = format!(
+                "Not playing, {} is invalid ({:?}).",
+                self.keyframes[*i].name, violation
+            );
+            return;
+        }
+        self.status.clear();
+        self.playback = Some(Playback {
+            target: 0,
+            paused: false,
+            from: current,
+            progress: 0.0,
+            last_tick: Instant::now(),
+        });
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if let Some(playback) = self.playback.as_mut() {
+            playback.paused = !playback.paused;
+            playback.last_tick = Instant::now();
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if let Some(playback) = self.playback.as_mut() {
+            playback.paused = true;
+        }
+    }
+
+    pub fn abort(&mut self) {
+        self.playback = None;
+    }
+
+    /// Advance playback by the time since the last call. Every interpolated
+    /// pose is checked against the limits and playback is aborted on the
+    /// first one that breaks them.
+    pub fn step(
+        &mut self,
+        geometry: &ArmGeometry,
+        elbow: ElbowConfig,
+        limits: &ArmLimits,
+    ) -> Option<PlaybackStep> {
+        let playback = self.playback.as_mut()?;
+
+        let now = Instant::now();
+        if !playback.paused {
+            playback.progress += (now - playback.last_tick).as_secs_f32();
+        }
+        playback.last_tick = now;
+
+        let Some(target) = self.keyframes.get(playback.target) else {
+            self.playback = None;
+            return None;
+        };
+
+        let t = if target.duration > 0.0 {
+            (playback.progress / target.duration).min(1.0)
+        } else {
+            1.0
+        };
+        let (arm_r, arm_h) = interpolate(
+            self.interpolation,
+            geometry,
+            elbow,
+            &playback.from,
+            target,
+            t,
+        );
+        if let Err(violation) = limits.check(geometry, arm_r as f64, arm_h as f64, elbow) {
+            self.playback = None;
+            self.status = format!(
+                "Playback aborted, ({:.2}, {:.2}) on the way to {} is invalid ({:?}).",
+                arm_r, arm_h, target.name, violation
+            );
+            return None;
+        }
+        let mut step = PlaybackStep {
+            arm_r,
+            arm_h,
+            turntable: lerp(playback.from.turntable, target.turntable, t),
+            claw_closed: None,
+        };
+
+        if t >= 1.0 {
+            step.claw_closed = Some(target.claw_closed);
+            playback.from = target.clone();
+            playback.progress = 0.0;
+            playback.target += 1;
+            if playback.target >= self.keyframes.len() {
+                self.playback = None;
+            }
+        }
+
+        Some(step)
+    }
+
+    pub fn save(&mut self) {
+        self.status = match serde_json::to_string_pretty(&self.keyframes) {
+            Ok(json) => match fs::write(&self.path, json) {
+                Ok(_) => format!("Saved {} keyframes.", self.keyframes.len()),
+                Err(e) => format!("Failed to save: {}", e),
+            },
+            Err(e) => format!("Failed to save: {}", e),
+        };
+    }
+
+    pub fn load(&mut self) {
+        let loaded = fs::read_to_string(&self.path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str::<Vec<Keyframe>>(&s).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(keyframes) => {
+                self.status = format!("Loaded {} keyframes.", keyframes.len());
+                self.keyframes = keyframes;
+                self.playback = None;
+            }
+            Err(e) => self.status = format!("Failed to load: {}", e),
+        }
+    }
+}
+
+fn check(
+    keyframe: &Keyframe,
+    geometry: &ArmGeometry,
+    elbow: ElbowConfig,
+    limits: &ArmLimits,
+) -> Result<JointAngles, Violation> {
+    limits.check(
+        geometry,
+        keyframe.arm_r as f64,
+        keyframe.arm_h as f64,
+        elbow,
+    )
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Claw position between two keyframes. Joint interpolation falls back to a
+/// straight line if either end can't be solved.
+fn interpolate(
+    interpolation: Interpolation,
+    geometry: &ArmGeometry,
+    elbow: ElbowConfig,
+    from: &Keyframe,
+    to: &Keyframe,
+    t: f32,
+) -> (f32, f32) {
+    let cartesian = (lerp(from.arm_r, to.arm_r, t), lerp(from.arm_h, to.arm_h, t));
+    if interpolation == Interpolation::Cartesian {
+        return cartesian;
+    }
+
+    match (
+        geometry.inverse(from.arm_r as f64, from.arm_h as f64, elbow),
+        geometry.inverse(to.arm_r as f64, to.arm_h as f64, elbow),
+    ) {
+        (Ok(a), Ok(b)) => {
+            let t = t as f64;
+            let claw = geometry.forward(JointAngles {
+                shoulder: a.shoulder + (b.shoulder - a.shoulder) * t,
+                elbow: a.elbow + (b.elbow - a.elbow) * t,
+            })[2];
+            (claw[0] as f32, claw[1] as f32)
+        }
+        _ => cartesian,
+    }
+}
@@ -0,0 +1,182 @@
+//! Software-rendered 3D view of the turntable and arm.
+//!
+//! Everything is projected on the CPU and drawn as line segments with the egui
+//! painter, so no GPU callbacks are needed. World coordinates are in arm plot
+//! units with x forward, y left and z up.
+
+use eframe::egui::{self, Color32, Pos2, Rect, Stroke};
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+pub struct OrbitCamera {
+    /// Rotation about the vertical axis, radians.
+    pub yaw: f32,
+    /// Elevation above the horizon, radians.
+    pub pitch: f32,
+    pub distance: f32,
+    pub target: [f32; 3],
+}
+
+/// Arm state to draw, in the same r-h coordinates as the 2D arm plot.
+pub struct ArmScene {
+    /// Turntable angle in degrees.
+    pub turntable: f32,
+    pub turntable_radius: f32,
+    pub commanded: Option<Vec<[f64; 2]>>,
+    pub measured: Option<Vec<[f64; 2]>>,
+    pub jaws: Vec<Vec<[f64; 2]>>,
+    pub reach: f32,
+}
+
+impl OrbitCamera {
+    pub fn new() -> Self {
+        Self {
+            yaw: -0.8,
+            pitch: 0.5,
+            distance: 45.0,
+            target: [4.0, 0.0, 7.0],
+        }
+    }
+
+    fn position(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        [
+            self.target[0] - self.distance * cos_pitch * cos_yaw,
+            self.target[1] - self.distance * cos_pitch * sin_yaw,
+            self.target[2] + self.distance * sin_pitch,
+        ]
+    }
+
+    /// Perspective projection into `rect`, or `None` behind the camera.
+    fn project(&self, p: [f32; 3], rect: Rect) -> Option<Pos2> {
+        let eye = self.position();
+        let forward = normalize(sub(self.target, eye));
+        let right = normalize(cross(forward, [0.0, 0.0, 1.0]));
+        let up = cross(right, forward);
+
+        let d = sub(p, eye);
+        let depth = dot(d, forward);
+        if depth < 0.1 {
+            return None;
+        }
+
+        let focal = 0.5 * rect.height() / (0.5 * FRAC_PI_2).tan();
+        Some(Pos2::new(
+            rect.center().x + focal * dot(d, right) / depth,
+            rect.center().y - focal * dot(d, up) / depth,
+        ))
+    }
+}
+
+/// Draw the scene into a square area and handle orbiting. Drag with the
+/// primary button to orbit, with the secondary button to zoom.
+pub fn show(ui: &mut egui::Ui, camera: &mut OrbitCamera, scene: &ArmScene) {
+    let size = ui.available_width().min(ui.available_height()).max(200.0);
+    let (response, painter) = ui.allocate_painter(egui::vec2(size, size), egui::Sense::drag());
+
+    if response.dragged_by(egui::PointerButton::Primary) {
+        let delta = response.drag_delta();
+        camera.yaw -= delta.x * 0.01;
+        camera.pitch = (camera.pitch + delta.y * 0.01).clamp(-1.4, 1.4);
+    }
+    if response.dragged_by(egui::PointerButton::Secondary) {
+        camera.distance =
+            (camera.distance * (1.0 + response.drag_delta().y * 0.01)).clamp(5.0, 200.0);
+    }
+
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+
+    let line = |points: &[[f32; 3]], stroke: Stroke| {
+        for pair in points.windows(2) {
+            if let (Some(a), Some(b)) =
+                (camera.project(pair[0], rect), camera.project(pair[1], rect))
+            {
+                painter.line_segment([a, b], stroke);
+            }
+        }
+    };
+
+    // Ground grid.
+    let grid_stroke = Stroke::new(1.0, Color32::from_gray(50));
+    let half = scene.reach.ceil();
+    let mut x = -half;
+    while x <= half {
+        line(&[[x, -half, 0.0], [x, half, 0.0]], grid_stroke);
+        line(&[[-half, x, 0.0], [half, x, 0.0]], grid_stroke);
+        x += 2.0;
+    }
+    line(
+        &[[0.0, 0.0, 0.0], [half, 0.0, 0.0]],
+        Stroke::new(2.0, Color32::from_rgb(120, 40, 40)),
+    );
+
+    // Reach circle and turntable.
+    line(
+        &circle(scene.reach, 0.0),
+        Stroke::new(1.0, Color32::from_rgba_unmultiplied(0, 200, 0, 80)),
+    );
+    line(
+        &circle(scene.turntable_radius, 0.0),
+        Stroke::new(2.0, Color32::GRAY),
+    );
+
+    // The arm moves in the vertical plane the turntable points along.
+    let (sin, cos) = scene.turntable.to_radians().sin_cos();
+    let to_world = |p: &[f64; 2]| [p[0] as f32 * cos, p[0] as f32 * sin, p[1] as f32];
+    let turntable_tip = to_world(&[scene.turntable_radius as f64, 0.0]);
+    line(
+        &[[0.0, 0.0, 0.0], turntable_tip],
+        Stroke::new(2.0, Color32::GRAY),
+    );
+
+    if let Some(measured) = &scene.measured {
+        let points: Vec<[f32; 3]> = measured.iter().map(to_world).collect();
+        if let Some(first) = points.first() {
+            line(&[[0.0, 0.0, 0.0], *first], Stroke::new(3.0, Color32::GRAY));
+        }
+        line(&points, Stroke::new(3.0, Color32::ORANGE));
+    }
+
+    if let Some(commanded) = &scene.commanded {
+        let points: Vec<[f32; 3]> = commanded.iter().map(to_world).collect();
+        if let Some(first) = points.first() {
+            line(&[[0.0, 0.0, 0.0], *first], Stroke::new(3.0, Color32::GRAY));
+        }
+        line(&points, Stroke::new(2.0, Color32::WHITE));
+        for jaw in &scene.jaws {
+            let jaw: Vec<[f32; 3]> = jaw.iter().map(to_world).collect();
+            line(&jaw, Stroke::new(2.0, Color32::LIGHT_BLUE));
+        }
+    }
+}
+
+fn circle(radius: f32, z: f32) -> Vec<[f32; 3]> {
+    (0..=48)
+        .map(|i| {
+            let a = i as f32 / 48.0 * TAU;
+            [radius * a.cos(), radius * a.sin(), z]
+        })
+        .collect()
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = dot(a, a).sqrt();
+    [a[0] / length, a[1] / length, a[2] / length]
+}
@@ -0,0 +1,122 @@
+//! Claw commands and grip feedback.
+
+use crate::ring_buffer::RingBuffer;
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+#[derive(PartialEq, Debug)]
+pub enum GripState {
+    /// No `CLAW` telemetry received yet.
+    NoFeedback,
+    Open,
+    /// Closed all the way without meeting resistance.
+    Empty,
+    /// Stalled short of fully closed while drawing current.
+    Gripping,
+}
+
+pub struct Claw {
+    /// Servo angles in degrees.
+    pub open_angle: f32,
+    pub closed_angle: f32,
+    pub commanded: f32,
+
+    pub measured_angle: Option<f32>,
+    pub measured_current: Option<f32>,
+    pub current_history: RingBuffer<f64>,
+
+    /// Current above which a stalled claw counts as holding something.
+    pub grip_current: f32,
+    /// How far short of `closed_angle` the claw must stop to count as a grip.
+    pub grip_margin: f32,
+}
+
+impl Claw {
+    pub fn new() -> Self {
+        Self {
+            open_angle: 90.0,
+            closed_angle: 0.0,
+            commanded: 90.0,
+            measured_angle: None,
+            measured_current: None,
+            current_history: RingBuffer::new(256),
+            grip_current: 0.3,
+            grip_margin: 5.0,
+        }
+    }
+
+    /// `CLAW SET <angle>`, remembering the commanded angle.
+    pub fn set_message(&mut self, angle: f32) -> [MsgElem; 3] {
+        self.commanded = angle;
+        [Code(CLAW), Code(SET), F32(angle)]
+    }
+
+    pub fn open_message(&mut self) -> [MsgElem; 3] {
+        self.set_message(self.open_angle)
+    }
+
+    pub fn close_message(&mut self) -> [MsgElem; 3] {
+        self.set_message(self.closed_angle)
+    }
+
+    pub fn toggle_message(&mut self) -> [MsgElem; 3] {
+        if self.is_closed() {
+            self.open_message()
+        } else {
+            self.close_message()
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        (self.commanded - self.closed_angle).abs() < (self.commanded - self.open_angle).abs()
+    }
+
+    pub fn record(&mut self, angle: f32, current: f32) {
+        self.measured_angle = Some(angle);
+        self.measured_current = Some(current);
+        self.current_history.push(current as f64);
+    }
+
+    /// How open the claw is, 0 closed to 1 open. Uses telemetry if there is
+    /// any.
+    pub fn openness(&self) -> f32 {
+        let angle = self.measured_angle.unwrap_or(self.commanded);
+        let range = self.open_angle - self.closed_angle;
+        if range == 0.0 {
+            return 0.0;
+        }
+        ((angle - self.closed_angle) / range).clamp(0.0, 1.0)
+    }
+
+    pub fn grip(&self) -> GripState {
+        let (Some(angle), Some(current)) = (self.measured_angle, self.measured_current) else {
+            return GripState::NoFeedback;
+        };
+
+        if !self.is_closed() {
+            GripState::Open
+        } else if current >= self.grip_current
+            && (angle - self.closed_angle).abs() > self.grip_margin
+        {
+            GripState::Gripping
+        } else {
+            GripState::Empty
+        }
+    }
+
+    /// Two jaw lines drawn at the end of the forearm.
+    pub fn jaws(&self, elbow: [f64; 2], claw: [f64; 2], length: f64) -> [Vec<[f64; 2]>; 2] {
+        let heading = (claw[1] - elbow[1]).atan2(claw[0] - elbow[0]);
+        let spread = 0.1 + 0.5 * self.openness() as f64;
+        let jaw = |angle: f64| {
+            vec![
+                claw,
+                [
+                    claw[0] + length * angle.cos(),
+                    claw[1] + length * angle.sin(),
+                ],
+            ]
+        };
+        [jaw(heading + spread), jaw(heading - spread)]
+    }
+}
@@ -0,0 +1,150 @@
+//! On-screen differential-drive control using `DRIVE_BASE SET LEFT .. RIGHT ..`.
+
+use eframe::egui::{self, Color32, Stroke};
+use std::time::{Duration, Instant};
+
+use crate::ring_buffer::RingBuffer;
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+const SEND_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum DriveMode {
+    /// One stick: forward/back is linear velocity, left/right is turn rate.
+    Arcade,
+    /// One vertical stick per wheel.
+    Tank,
+}
+
+pub struct DriveTeleop {
+    pub mode: DriveMode,
+    /// Linear speed at full deflection, m/s.
+    pub max_speed: f32,
+    /// Turn rate at full deflection, rad/s.
+    pub max_turn: f32,
+
+    /// Stick positions in [-1, 1], x right and y up. Tank mode uses the y of
+    /// each.
+    pub left_stick: [f32; 2],
+    pub right_stick: [f32; 2],
+
+    /// Last left/right wheel speeds sent.
+    pub commanded: [f32; 2],
+    pub measured: Option<[f32; 2]>,
+    /// Commanded left, commanded right, measured left, measured right.
+    pub history: RingBuffer<[f64; 4]>,
+
+    last_send: Instant,
+    // Whether the last command was non-zero, so a stop gets sent once.
+    driving: bool,
+}
+
+impl DriveTeleop {
+    pub fn new() -> Self {
+        Self {
+            mode: DriveMode::Arcade,
+            max_speed: 0.3,
+            max_turn: 2.0,
+            left_stick: [0.0, 0.0],
+            right_stick: [0.0, 0.0],
+            commanded: [0.0, 0.0],
+            measured: None,
+            history: RingBuffer::new(512),
+            last_send: Instant::now(),
+            driving: false,
+        }
+    }
+
+    /// Left and right wheel speeds for the current stick positions.
+    pub fn wheel_speeds(&self, wheelbase: f32) -> [f32; 2] {
+        match self.mode {
+            DriveMode::Arcade => {
+                let v = self.left_stick[1] * self.max_speed;
+                // Stick right turns clockwise, i.e. negative angular velocity.
+                let w = -self.left_stick[0] * self.max_turn;
+                [v - 0.5 * w * wheelbase, v + 0.5 * w * wheelbase]
+            }
+            DriveMode::Tank => [
+                self.left_stick[1] * self.max_speed,
+                self.right_stick[1] * self.max_speed,
+            ],
+        }
+    }
+
+    /// Command to send this frame, if any. Rate limited, and sends a single
+    /// stop once the sticks are released.
+    pub fn update(&mut self, wheelbase: f32) -> Option<Vec<MsgElem>> {
+        if self.last_send.elapsed() < SEND_INTERVAL {
+            return None;
+        }
+
+        let speeds = self.wheel_speeds(wheelbase);
+        let moving = speeds != [0.0, 0.0];
+        if !moving && !self.driving {
+            return None;
+        }
+
+        self.last_send = Instant::now();
+        self.driving = moving;
+        self.commanded = speeds;
+        Some(vec![
+            Code(DRIVE_BASE),
+            Code(SET),
+            Code(LEFT),
+            F32(speeds[0]),
+            Code(RIGHT),
+            F32(speeds[1]),
+        ])
+    }
+
+    pub fn record(&mut self, left: f32, right: f32) {
+        self.measured = Some([left, right]);
+        self.history.push([
+            self.commanded[0] as f64,
+            self.commanded[1] as f64,
+            left as f64,
+            right as f64,
+        ]);
+    }
+}
+
+/// Virtual joystick that springs back to the centre when released. With
+/// `vertical_only` the stick can only move up and down.
+pub fn joystick(ui: &mut egui::Ui, value: &mut [f32; 2], size: f32, vertical_only: bool) {
+    let (response, painter) =
+        ui.allocate_painter(egui::vec2(size, size), egui::Sense::click_and_drag());
+    let rect = response.rect;
+    let radius = 0.5 * size - 8.0;
+
+    match response.interact_pointer_pos() {
+        Some(pointer) if response.is_pointer_button_down_on() => {
+            let offset = (pointer - rect.center()) / radius;
+            let offset = if vertical_only {
+                egui::vec2(0.0, offset.y)
+            } else {
+                offset
+            };
+            let offset = if offset.length() > 1.0 {
+                offset.normalized()
+            } else {
+                offset
+            };
+            *value = [offset.x, -offset.y];
+        }
+        _ => *value = [0.0, 0.0],
+    }
+
+    painter.circle_stroke(rect.center(), radius, Stroke::new(2.0, Color32::GRAY));
+    if vertical_only {
+        painter.line_segment(
+            [
+                rect.center() - egui::vec2(0.0, radius),
+                rect.center() + egui::vec2(0.0, radius),
+            ],
+            Stroke::new(1.0, Color32::DARK_GRAY),
+        );
+    }
+    let knob = rect.center() + egui::vec2(value[0], -value[1]) * radius;
+    painter.circle_filled(knob, 12.0, Color32::LIGHT_BLUE);
+}
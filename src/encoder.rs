@@ -0,0 +1,145 @@
+//! Encoder motor angle/velocity telemetry and counts-per-revolution
+//! calibration.
+//!
+//! The firmware reports `ENCODER_MOTOR ANGLE <value>` and
+//! `ENCODER_MOTOR VELOCITY <value>`, either in raw ticks or converted to
+//! degrees depending on the last `ENCODER_MOTOR GET RAW|CONVERTED`.
+
+use std::time::{Duration, Instant};
+
+use crate::ring_buffer::RingBuffer;
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::{self, *};
+
+pub struct Encoder {
+    /// `RAW` or `CONVERTED`, whichever the firmware was last asked for.
+    pub units: MessageCode,
+    pub angle: Option<f32>,
+    pub velocity: Option<f32>,
+    /// Seconds since start and value.
+    pub angle_history: RingBuffer<[f64; 2]>,
+    pub velocity_history: RingBuffer<[f64; 2]>,
+
+    /// Ask for angle and velocity every `poll_interval_ms`.
+    pub polling: bool,
+    pub poll_interval_ms: u32,
+    last_poll: Instant,
+
+    pub counts_per_rev: f32,
+    pub calibration_turns: f32,
+    /// Raw count when the calibration started.
+    pub calibration_start: Option<f32>,
+    pub status: String,
+
+    start: Instant,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self {
+            units: RAW,
+            angle: None,
+            velocity: None,
+            angle_history: RingBuffer::new(1024),
+            velocity_history: RingBuffer::new(1024),
+            polling: false,
+            poll_interval_ms: 50,
+            last_poll: Instant::now(),
+            counts_per_rev: 1.0,
+            calibration_turns: 10.0,
+            calibration_start: None,
+            status: String::new(),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn record_angle(&mut self, angle: f32) {
+        self.angle = Some(angle);
+        self.angle_history
+            .push([self.start.elapsed().as_secs_f64(), angle as f64]);
+    }
+
+    pub fn record_velocity(&mut self, velocity: f32) {
+        self.velocity = Some(velocity);
+        self.velocity_history
+            .push([self.start.elapsed().as_secs_f64(), velocity as f64]);
+    }
+
+    /// `ENCODER_MOTOR GET RAW` or `ENCODER_MOTOR GET CONVERTED`. Clears the
+    /// readings and history so nothing mixes units.
+    pub fn units_message(&mut self, units: MessageCode) -> [MsgElem; 3] {
+        self.units = units;
+        self.angle = None;
+        self.velocity = None;
+        self.angle_history.clear();
+        self.velocity_history.clear();
+        self.calibration_start = None;
+        [Code(ENCODER_MOTOR), Code(GET), Code(units)]
+    }
+
+    /// Angle and velocity requests, once the poll interval has passed.
+    pub fn poll(&mut self) -> Option<[[MsgElem; 3]; 2]> {
+        let interval = Duration::from_millis(self.poll_interval_ms as u64);
+        if !self.polling || self.last_poll.elapsed() < interval {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        Some([
+            [Code(ENCODER_MOTOR), Code(GET), Code(ANGLE)],
+            [Code(ENCODER_MOTOR), Code(GET), Code(VELOCITY)],
+        ])
+    }
+
+    /// Raw ticks in degrees using the current counts per revolution.
+    pub fn to_degrees(&self, ticks: f32) -> f32 {
+        ticks / self.counts_per_rev * 360.0
+    }
+
+    pub fn start_calibration(&mut self) {
+        if self.units != RAW {
+            self.status = String::from("Switch to RAW before calibrating.");
+            return;
+        }
+        match self.angle {
+            Some(angle) => {
+                self.calibration_start = Some(angle);
+                self.status = format!(
+                    "Rotate the output {} turns, then finish.",
+                    self.calibration_turns
+                );
+            }
+            None => self.status = String::from("No encoder reading yet."),
+        }
+    }
+
+    pub fn finish_calibration(&mut self) {
+        let (Some(start), Some(end)) = (self.calibration_start.take(), self.angle) else {
+            return;
+        };
+        let counts = (end - start).abs();
+        if self.calibration_turns <= 0.0 || counts == 0.0 {
+            self.status = String::from("Encoder didn't move.");
+            return;
+        }
+        self.counts_per_rev = counts / self.calibration_turns;
+        self.status = format!(
+            "{:.0} counts over {} turns: {:.2} counts per revolution.",
+            counts, self.calibration_turns, self.counts_per_rev
+        );
+    }
+
+    /// `ENCODER_MOTOR SET CONVERTED <counts per revolution>`, or `None` if
+    /// the count isn't positive.
+    pub fn counts_per_rev_message(&mut self) -> Option<[MsgElem; 4]> {
+        if self.counts_per_rev.is_nan() || self.counts_per_rev <= 0.0 {
+            self.status = String::from("Counts per revolution must be positive.");
+            return None;
+        }
+        Some([
+            Code(ENCODER_MOTOR),
+            Code(SET),
+            Code(CONVERTED),
+            F32(self.counts_per_rev),
+        ])
+    }
+}
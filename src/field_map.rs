@@ -0,0 +1,180 @@
+//! Competition field map drawn under the odometry traces.
+//!
+//! Maps are JSON files in metres, e.g.
+//!
+//! ```json
+//! {
+//!     "lines": [[[0.0, 0.0], [2.4, 0.0]]],
+//!     "tape": [[[0.3, 0.3], [1.2, 0.3], [1.2, 1.5]]],
+//!     "walls": [[[0.0, 0.0], [0.0, 2.4], [2.4, 2.4]]],
+//!     "beacons": [{ "name": "A", "position": [2.4, 1.2] }]
+//! }
+//! ```
+//!
+//! Every key is optional.
+
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Deserialize, Default)]
+pub struct FieldMap {
+    /// Plain reference line segments.
+    #[serde(default)]
+    pub lines: Vec<[[f64; 2]; 2]>,
+    /// Tape paths, as polylines.
+    #[serde(default)]
+    pub tape: Vec<Vec<[f64; 2]>>,
+    /// Walls, as polylines.
+    #[serde(default)]
+    pub walls: Vec<Vec<[f64; 2]>>,
+    #[serde(default)]
+    pub beacons: Vec<Beacon>,
+}
+
+#[derive(Deserialize)]
+pub struct Beacon {
+    #[serde(default)]
+    pub name: String,
+    pub position: [f64; 2],
+}
+
+impl FieldMap {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&contents).map_err(|e| e.to_string())
+    }
+}
+
+/// Rigid transform from map coordinates to raw odometry coordinates.
+#[derive(Clone, Copy)]
+pub struct MapAlignment {
+    pub angle: f64,
+    pub offset: [f64; 2],
+}
+
+impl MapAlignment {
+    pub fn identity() -> Self {
+        Self {
+            angle: 0.0,
+            offset: [0.0, 0.0],
+        }
+    }
+
+    /// Find the alignment that takes map points `map[0]`, `map[1]` onto
+    /// odometry points `odo[0]`, `odo[1]`. The first pair is matched exactly,
+    /// the second only fixes the rotation since the map is already in metres.
+    pub fn from_reference_points(map: [[f64; 2]; 2], odo: [[f64; 2]; 2]) -> Option<Self> {
+        let map_dir = [map[1][0] - map[0][0], map[1][1] - map[0][1]];
+        let odo_dir = [odo[1][0] - odo[0][0], odo[1][1] - odo[0][1]];
+
+        if map_dir[0].hypot(map_dir[1]) < 1e-6 || odo_dir[0].hypot(odo_dir[1]) < 1e-6 {
+            return None;
+        }
+
+        let angle = odo_dir[1].atan2(odo_dir[0]) - map_dir[1].atan2(map_dir[0]);
+        let rotated = Self {
+            angle,
+            offset: [0.0, 0.0],
+        }
+        .apply(map[0]);
+
+        Some(Self {
+            angle,
+            offset: [odo[0][0] - rotated[0], odo[0][1] - rotated[1]],
+        })
+    }
+
+    pub fn apply(&self, p: [f64; 2]) -> [f64; 2] {
+        let (sin, cos) = self.angle.sin_cos();
+        [
+            cos * p[0] - sin * p[1] + self.offset[0],
+            sin * p[0] + cos * p[1] + self.offset[1],
+        ]
+    }
+
+    pub fn invert(&self, p: [f64; 2]) -> [f64; 2] {
+        let (sin, cos) = self.angle.sin_cos();
+        let dx = p[0] - self.offset[0];
+        let dy = p[1] - self.offset[1];
+        [cos * dx + sin * dy, -sin * dx + cos * dy]
+    }
+}
+
+/// Loaded map plus the state needed to line it up with odometry.
+pub struct FieldMapOverlay {
+    pub path: String,
+    pub status: String,
+    pub map: Option<FieldMap>,
+    pub show: bool,
+    pub alignment: MapAlignment,
+    /// Reference points picked so far, in order map A, odometry A, map B,
+    /// odometry B.
+    pub picks: Vec<[f64; 2]>,
+}
+
+impl FieldMapOverlay {
+    pub fn new() -> Self {
+        Self {
+            path: String::new(),
+            status: String::new(),
+            map: None,
+            show: true,
+            alignment: MapAlignment::identity(),
+            picks: Vec::new(),
+        }
+    }
+
+    pub fn load(&mut self) {
+        match FieldMap::load(&self.path) {
+            Ok(map) => {
+                self.status = format!(
+                    "Loaded {} lines, {} tape paths, {} walls, {} beacons.",
+                    map.lines.len(),
+                    map.tape.len(),
+                    map.walls.len(),
+                    map.beacons.len()
+                );
+                self.map = Some(map);
+            }
+            Err(e) => self.status = format!("Failed to load map: {}", e),
+        }
+    }
+
+    pub fn next_pick_label(&self) -> &'static str {
+        match self.picks.len() {
+            0 => "Click the first reference point on the map.",
+            1 => "Click where the first reference point is on the odometry trace.",
+            2 => "Click the second reference point on the map.",
+            _ => "Click where the second reference point is on the odometry trace.",
+        }
+    }
+
+    /// Record a picked point (raw odometry coordinates). Returns true once
+    /// all four points are in and the alignment has been updated.
+    pub fn add_pick(&mut self, p: [f64; 2]) -> bool {
+        // Map picks are clicked on the map as currently drawn, so undo the
+        // current alignment to get map coordinates.
+        if self.picks.len().is_multiple_of(2) {
+            self.picks.push(self.alignment.invert(p));
+        } else {
+            self.picks.push(p);
+        }
+
+        if self.picks.len() < 4 {
+            return false;
+        }
+
+        match MapAlignment::from_reference_points(
+            [self.picks[0], self.picks[2]],
+            [self.picks[1], self.picks[3]],
+        ) {
+            Some(alignment) => {
+                self.alignment = alignment;
+                self.status = String::from("Map aligned.");
+            }
+            None => self.status = String::from("Reference points too close together."),
+        }
+        self.picks.clear();
+        true
+    }
+}
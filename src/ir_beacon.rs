@@ -0,0 +1,221 @@
+//! IR beacon receiver strengths and the bearing estimated from them.
+//!
+//! `IR_BEACON` telemetry carries one strength per receiver. The receivers sit
+//! around the robot at `receiver_angles`, and the bearing is the direction of
+//! the strength-weighted sum of their unit vectors.
+
+use eframe::egui::{self, Color32, Stroke};
+
+use crate::odometry::Pos;
+use crate::ring_buffer::RingBuffer;
+
+/// A bearing taken from a point, in raw odometry coordinates.
+pub struct BearingLine {
+    pub origin: [f64; 2],
+    /// World-frame bearing in radians.
+    pub bearing: f64,
+}
+
+impl BearingLine {
+    pub fn points(&self, length: f64) -> Vec<[f64; 2]> {
+        vec![
+            self.origin,
+            [
+                self.origin[0] + length * self.bearing.cos(),
+                self.origin[1] + length * self.bearing.sin(),
+            ],
+        ]
+    }
+}
+
+pub struct IrBeacon {
+    pub strengths: Vec<f32>,
+    /// Receiver directions in degrees, counter-clockwise from the front.
+    pub receiver_angles: Vec<f32>,
+    /// Below this total strength there is no bearing.
+    pub min_strength: f32,
+
+    samples: usize,
+    /// Sample number and strengths.
+    pub history: RingBuffer<(usize, Vec<f32>)>,
+    /// Sample number and robot-frame bearing in degrees.
+    pub bearing_history: RingBuffer<[f64; 2]>,
+
+    pub show_on_map: bool,
+    /// Length of the bearing lines drawn on the odometry map, metres.
+    pub line_length: f32,
+    /// Bearings marked for triangulation.
+    pub fixes: Vec<BearingLine>,
+    pub status: String,
+}
+
+impl IrBeacon {
+    pub fn new() -> Self {
+        Self {
+            strengths: Vec::new(),
+            receiver_angles: Vec::new(),
+            min_strength: 0.1,
+            samples: 0,
+            history: RingBuffer::new(512),
+            bearing_history: RingBuffer::new(512),
+            show_on_map: true,
+            line_length: 3.0,
+            fixes: Vec::new(),
+            status: String::new(),
+        }
+    }
+
+    /// Place `count` receivers evenly around the robot, the first at the front.
+    pub fn space_evenly(&mut self, count: usize) {
+        let step = 360.0 / count.max(1) as f32;
+        self.receiver_angles = (0..count).map(|i| i as f32 * step).collect();
+    }
+
+    pub fn record(&mut self, strengths: Vec<f32>) {
+        // Assume evenly spaced receivers until told otherwise, but never
+        // overwrite angles the user has entered.
+        if self.receiver_angles.is_empty() {
+            self.space_evenly(strengths.len());
+        }
+        self.status = if self.receiver_angles.len() == strengths.len() {
+            String::new()
+        } else {
+            format!(
+                "Got {} strengths for {} receiver angles, no bearing until they match.",
+                strengths.len(),
+                self.receiver_angles.len()
+            )
+        };
+
+        self.samples += 1;
+        self.history.push((self.samples, strengths.clone()));
+        self.strengths = strengths;
+        if let Some(bearing) = self.bearing() {
+            self.bearing_history
+                .push([self.samples as f64, bearing.to_degrees() as f64]);
+        }
+    }
+
+    /// Robot-frame bearing in radians, if the signal is strong enough.
+    pub fn bearing(&self) -> Option<f32> {
+        if self.strengths.len() != self.receiver_angles.len() {
+            return None;
+        }
+        let total: f32 = self.strengths.iter().sum();
+        if total < self.min_strength {
+            return None;
+        }
+
+        let (x, y) =
+            self.strengths
+                .iter()
+                .zip(&self.receiver_angles)
+                .fold((0.0, 0.0), |(x, y), (s, a)| {
+                    let (sin, cos) = a.to_radians().sin_cos();
+                    (x + s * cos, y + s * sin)
+                });
+        // Opposite receivers cancel, up to rounding in sin/cos.
+        if x.hypot(y) <= 1e-6 * total {
+            return None;
+        }
+        Some(y.atan2(x))
+    }
+
+    pub fn line_of_bearing(&self, pos: &Pos) -> Option<BearingLine> {
+        self.bearing().map(|bearing| BearingLine {
+            origin: [pos.x as f64, pos.y as f64],
+            bearing: (pos.theta + bearing) as f64,
+        })
+    }
+}
+
+/// Compass with the robot's front at the top, a dot per receiver shaded by
+/// strength, and a needle towards the beacon.
+pub fn compass(ui: &mut egui::Ui, beacon: &IrBeacon, size: f32) {
+    let (response, painter) = ui.allocate_painter(egui::vec2(size, size), egui::Sense::hover());
+    let center = response.rect.center();
+    let radius = 0.5 * size - 12.0;
+    // Robot frame: 0° is up and angles increase counter-clockwise.
+    let direction = |angle: f32| egui::vec2(-angle.sin(), -angle.cos());
+
+    painter.circle_stroke(center, radius, Stroke::new(2.0, Color32::GRAY));
+    painter.text(
+        center + direction(0.0) * (radius - 10.0),
+        egui::Align2::CENTER_CENTER,
+        "Front",
+        egui::FontId::proportional(12.0),
+        Color32::GRAY,
+    );
+
+    let max = beacon.strengths.iter().copied().fold(0.0, f32::max);
+    for (strength, angle) in beacon.strengths.iter().zip(&beacon.receiver_angles) {
+        let level = if max > 0.0 { strength / max } else { 0.0 };
+        painter.circle_filled(
+            center + direction(angle.to_radians()) * radius,
+            6.0,
+            Color32::from_rgb((60.0 + 195.0 * level) as u8, 40, 40),
+        );
+    }
+
+    if let Some(bearing) = beacon.bearing() {
+        painter.arrow(
+            center,
+            direction(bearing) * (radius - 20.0),
+            Stroke::new(3.0, Color32::RED),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beacon(strengths: [f32; 4]) -> IrBeacon {
+        let mut beacon = IrBeacon::new();
+        beacon.record(strengths.to_vec());
+        beacon
+    }
+
+    fn assert_bearing(strengths: [f32; 4], degrees: f32) {
+        let bearing = beacon(strengths).bearing().unwrap().to_degrees();
+        assert!(
+            (bearing - degrees).abs() < 1e-3,
+            "{:?} gave {}°, expected {}°",
+            strengths,
+            bearing,
+            degrees
+        );
+    }
+
+    #[test]
+    fn four_receivers_weighted_bearing() {
+        // Receivers at 0°, 90°, 180° and 270°.
+        assert_bearing([1.0, 0.0, 0.0, 0.0], 0.0);
+        assert_bearing([0.0, 1.0, 0.0, 0.0], 90.0);
+        assert_bearing([0.0, 0.0, 0.0, 1.0], -90.0);
+        assert_bearing([1.0, 1.0, 0.0, 0.0], 45.0);
+        assert_bearing([3.0, 1.0, 0.0, 0.0], 1f32.atan2(3.0).to_degrees());
+        // Opposite receivers cancel.
+        assert_bearing([2.0, 1.0, 1.0, 0.0], 45.0);
+    }
+
+    #[test]
+    fn no_bearing_without_signal() {
+        assert!(beacon([0.0; 4]).bearing().is_none());
+        assert!(beacon([0.5, 0.0, 0.5, 0.0]).bearing().is_none());
+    }
+
+    #[test]
+    fn receiver_angles_are_kept() {
+        let mut beacon = IrBeacon::new();
+        beacon.receiver_angles = vec![10.0, 100.0, 190.0, 280.0];
+        beacon.record(vec![1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(beacon.receiver_angles, vec![10.0, 100.0, 190.0, 280.0]);
+        assert!((beacon.bearing().unwrap().to_degrees() - 10.0).abs() < 1e-3);
+
+        beacon.record(vec![1.0, 0.0, 0.0]);
+        assert_eq!(beacon.receiver_angles.len(), 4);
+        assert!(beacon.bearing().is_none());
+        assert!(!beacon.status.is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+//! Small dense least-squares solver for the calibration fits.
+
+/// Solve `rows * x ≈ targets` in the least-squares sense. Forms the normal
+/// equations and solves them by Gaussian elimination with partial pivoting,
+/// which is fine for the handful of unknowns the calibrations use. `None` if
+/// there are too few rows or the system is singular.
+pub fn least_squares(rows: &[Vec<f64>], targets: &[f64]) -> Option<Vec<f64>> {
+    let n = rows.first()?.len();
+    if rows.len() < n {
+        return None;
+    }
+
+    // Augmented matrix [A^T A | A^T b].
+    let mut m = vec![vec![0.0; n + 1]; n];
+    for (row, &target) in rows.iter().zip(targets) {
+        for i in 0..n {
+            for j in 0..n {
+                m[i][j] += row[i] * row[j];
+            }
+            m[i][n] += row[i] * target;
+        }
+    }
+
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+
+        let pivot_row = m[col].clone();
+        for (i, row) in m.iter_mut().enumerate() {
+            if i != col {
+                let factor = row[col] / pivot_row[col];
+                for (x, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                    *x -= factor * p;
+                }
+            }
+        }
+    }
+
+    Some((0..n).map(|i| m[i][n] / m[i][i]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn powers(x: f64, degree: usize) -> Vec<f64> {
+        (0..=degree).map(|i| x.powi(i as i32)).collect()
+    }
+
+    #[test]
+    fn recovers_exact_polynomial() {
+        let coefficients = [3.0, -2.0, 0.5];
+        let xs = [-2.0, -1.0, 0.0, 1.5, 4.0, 7.0];
+        let rows: Vec<Vec<f64>> = xs.iter().map(|&x| powers(x, 2)).collect();
+        let targets: Vec<f64> = rows
+            .iter()
+            .map(|row| row.iter().zip(&coefficients).map(|(a, c)| a * c).sum())
+            .collect();
+
+        let solution = least_squares(&rows, &targets).unwrap();
+        for (a, b) in solution.iter().zip(&coefficients) {
+            assert!((a - b).abs() < 1e-9, "{:?} != {:?}", solution, coefficients);
+        }
+    }
+
+    #[test]
+    fn too_few_rows() {
+        let rows = vec![powers(1.0, 2), powers(2.0, 2)];
+        assert!(least_squares(&rows, &[1.0, 2.0]).is_none());
+        assert!(least_squares(&[], &[]).is_none());
+    }
+
+    #[test]
+    fn singular_system() {
+        // Plenty of rows, but all at the same x.
+        let rows = vec![powers(2.0, 1); 5];
+        assert!(least_squares(&rows, &[1.0; 5]).is_none());
+    }
+}
@@ -0,0 +1,177 @@
+//! Lidar distance calibration against targets at known distances.
+//!
+//! For each known distance a batch of raw `LIDAR` readings is averaged, then a
+//! polynomial mapping raw readings to true distance is fitted by least
+//! squares. The coefficients are sent with `LIDAR SET CONVERTED`, after which
+//! the firmware can stream either `RAW` or `CONVERTED` distances.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::least_squares::least_squares;
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::{self, *};
+
+/// Highest polynomial degree the firmware accepts.
+pub const MAX_DEGREE: usize = 2;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CalibrationPoint {
+    pub known: f32,
+    /// Averaged raw reading.
+    pub measured: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CalibrationFile {
+    points: Vec<CalibrationPoint>,
+    coefficients: Vec<f64>,
+}
+
+pub struct LidarCalibration {
+    pub points: Vec<CalibrationPoint>,
+    /// Known distance for the next capture.
+    pub target: f32,
+    pub samples_per_point: usize,
+    /// Readings collected so far for the capture in progress.
+    pub capture: Option<Vec<f32>>,
+    pub degree: usize,
+    /// Correction coefficients, constant term first.
+    pub coefficients: Vec<f64>,
+    /// Which distances the firmware has been asked to stream.
+    pub stream: MessageCode,
+    pub path: String,
+    pub status: String,
+}
+
+impl LidarCalibration {
+    pub fn new() -> Self {
+        Self {
+            points: Vec::new(),
+            target: 100.0,
+            samples_per_point: 50,
+            capture: None,
+            degree: 1,
+            coefficients: vec![0.0, 1.0],
+            stream: RAW,
+            path: String::from("lidar_calibration.json"),
+            status: String::new(),
+        }
+    }
+
+    pub fn start_capture(&mut self) {
+        self.capture = Some(Vec::with_capacity(self.samples_per_point));
+    }
+
+    /// Feed a reading to the capture in progress, if any.
+    pub fn record(&mut self, distance: f32) {
+        let Some(samples) = self.capture.as_mut() else {
+            return;
+        };
+        samples.push(distance);
+
+        if samples.len() >= self.samples_per_point {
+            let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+            self.points.push(CalibrationPoint {
+                known: self.target,
+                measured: mean,
+            });
+            self.capture = None;
+            self.status = format!("Captured {:.2} at {:.2}.", mean, self.target);
+        }
+    }
+
+    pub fn fit(&mut self) {
+        let xs: Vec<f64> = self.points.iter().map(|p| p.measured as f64).collect();
+        let ys: Vec<f64> = self.points.iter().map(|p| p.known as f64).collect();
+
+        match fit_polynomial(&xs, &ys, self.degree) {
+            Some(coefficients) => {
+                self.coefficients = coefficients;
+                self.status = format!("Fitted degree {} correction.", self.degree);
+            }
+            None => {
+                self.status = format!(
+                    "Need at least {} distinct points for degree {}.",
+                    self.degree + 1,
+                    self.degree
+                )
+            }
+        }
+    }
+
+    pub fn apply(&self, raw: f64) -> f64 {
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(0.0, |acc, c| acc * raw + c)
+    }
+
+    /// Known minus corrected distance for each point.
+    pub fn residuals(&self) -> Vec<f64> {
+        self.points
+            .iter()
+            .map(|p| p.known as f64 - self.apply(p.measured as f64))
+            .collect()
+    }
+
+    /// `LIDAR SET CONVERTED <c0> <c1> <c2>`, padded with zeros.
+    pub fn coefficients_message(&self) -> Vec<MsgElem> {
+        let mut message = vec![Code(LIDAR), Code(SET), Code(CONVERTED)];
+        message.extend(
+            (0..=MAX_DEGREE).map(|i| F32(self.coefficients.get(i).copied().unwrap_or(0.0) as f32)),
+        );
+        message
+    }
+
+    /// `LIDAR GET RAW` or `LIDAR GET CONVERTED` selects what gets streamed.
+    pub fn stream_message(&mut self, stream: MessageCode) -> [MsgElem; 3] {
+        self.stream = stream;
+        [Code(LIDAR), Code(GET), Code(stream)]
+    }
+
+    pub fn save(&mut self) {
+        let file = CalibrationFile {
+            points: self.points.clone(),
+            coefficients: self.coefficients.clone(),
+        };
+        self.status = match serde_json::to_string_pretty(&file) {
+            Ok(json) => match fs::write(&self.path, json) {
+                Ok(_) => format!("Saved to {}.", self.path),
+                Err(e) => format!("Failed to save: {}", e),
+            },
+            Err(e) => format!("Failed to save: {}", e),
+        };
+    }
+
+    pub fn load(&mut self) {
+        let loaded = fs::read_to_string(&self.path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str::<CalibrationFile>(&s).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(file) if !(2..=MAX_DEGREE + 1).contains(&file.coefficients.len()) => {
+                self.status = format!(
+                    "Failed to load: {} coefficients, expected 2 to {}.",
+                    file.coefficients.len(),
+                    MAX_DEGREE + 1
+                );
+            }
+            Ok(file) => {
+                self.points = file.points;
+                self.degree = file.coefficients.len() - 1;
+                self.coefficients = file.coefficients;
+                self.status = format!("Loaded {}.", self.path);
+            }
+            Err(e) => self.status = format!("Failed to load: {}", e),
+        }
+    }
+}
+
+/// Least-squares polynomial fit, constant term first.
+fn fit_polynomial(xs: &[f64], ys: &[f64], degree: usize) -> Option<Vec<f64>> {
+    let rows: Vec<Vec<f64>> = xs
+        .iter()
+        .map(|&x| (0..=degree).map(|i| x.powi(i as i32)).collect())
+        .collect();
+    least_squares(&rows, ys)
+}
@@ -0,0 +1,108 @@
+//! Lidar log captured on the robot and streamed back as `LIDAR ALL`.
+//!
+//! Each log message carries one or more distance/convolution pairs:
+//! `LIDAR ALL <distance> <convolution> [<distance> <convolution> ...]`.
+
+use std::fs;
+
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+pub struct LidarLog {
+    pub distance: Vec<f32>,
+    pub convolution: Vec<f32>,
+    pub capturing: bool,
+    pub path: String,
+    pub status: String,
+}
+
+impl LidarLog {
+    pub fn new() -> Self {
+        Self {
+            distance: Vec::new(),
+            convolution: Vec::new(),
+            capturing: false,
+            path: String::from("lidar_log.csv"),
+            status: String::new(),
+        }
+    }
+
+    /// Decode the values following `LIDAR ALL`. Returns false, keeping
+    /// nothing, if they aren't whole distance/convolution pairs.
+    pub fn decode(&mut self, values: &[MsgElem]) -> bool {
+        if values.is_empty() || !values.len().is_multiple_of(2) {
+            return false;
+        }
+
+        let mut pairs = Vec::with_capacity(values.len() / 2);
+        for pair in values.chunks(2) {
+            match pair {
+                [F32(distance), F32(convolution)] => pairs.push((*distance, *convolution)),
+                _ => return false,
+            }
+        }
+
+        for (distance, convolution) in pairs {
+            self.distance.push(distance);
+            self.convolution.push(convolution);
+        }
+        true
+    }
+
+    /// `LIDAR SET ALL 1` starts capturing on the robot, `0` stops it and
+    /// makes the firmware send what it logged. Set `capturing` once it has
+    /// actually been sent.
+    pub fn capture_message(&self, start: bool) -> [MsgElem; 4] {
+        [Code(LIDAR), Code(SET), Code(ALL), U32(start as u32)]
+    }
+
+    pub fn clear(&mut self) {
+        self.distance.clear();
+        self.convolution.clear();
+    }
+
+    pub fn save(&mut self) {
+        let mut csv = String::from("index,distance,convolution\n");
+        for (i, (distance, convolution)) in self.distance.iter().zip(&self.convolution).enumerate()
+        {
+            csv.push_str(&format!("{},{},{}\n", i, distance, convolution));
+        }
+
+        self.status = match fs::write(&self.path, csv) {
+            Ok(_) => format!("Saved {} samples.", self.distance.len()),
+            Err(e) => format!("Failed to save: {}", e),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_every_pair_in_a_message() {
+        let mut log = LidarLog::new();
+        assert!(log.decode(&[F32(1.0), F32(0.1)]));
+        assert!(log.decode(&[F32(2.0), F32(0.2), F32(3.0), F32(0.3), F32(4.0), F32(0.4)]));
+
+        assert_eq!(log.distance, vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(log.convolution, vec![0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn rejects_partial_pairs_and_keeps_nothing() {
+        let mut log = LidarLog::new();
+        assert!(!log.decode(&[]));
+        assert!(!log.decode(&[F32(1.0)]));
+        assert!(!log.decode(&[F32(1.0), F32(0.1), F32(2.0)]));
+        assert!(log.distance.is_empty() && log.convolution.is_empty());
+    }
+
+    #[test]
+    fn rejects_non_f32_values_and_keeps_nothing() {
+        let mut log = LidarLog::new();
+        assert!(!log.decode(&[F32(1.0), F32(0.1), U32(2), F32(0.2)]));
+        assert!(!log.decode(&[F32(1.0), F32(0.1), F32(2.0), Code(NONE)]));
+        assert!(log.distance.is_empty() && log.convolution.is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+//! Target detection on the lidar convolution signal.
+//!
+//! A peak starts when the signal rises to `threshold` and ends when it falls
+//! below `threshold - hysteresis`, so noise around the threshold doesn't split
+//! one target into several. Peaks narrower than `min_width` samples are
+//! dropped.
+
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+pub struct Peak {
+    /// First and one-past-last sample above the threshold.
+    pub start: usize,
+    pub end: usize,
+    /// Sample with the highest value.
+    pub max_index: usize,
+    pub max_value: f32,
+}
+
+pub struct PeakDetector {
+    pub threshold: f32,
+    pub hysteresis: f32,
+    pub min_width: u32,
+}
+
+impl PeakDetector {
+    pub fn new() -> Self {
+        Self {
+            threshold: 100.0,
+            hysteresis: 10.0,
+            min_width: 3,
+        }
+    }
+
+    pub fn detect(&self, samples: &[f32]) -> Vec<Peak> {
+        let mut peaks = Vec::new();
+        let mut current: Option<Peak> = None;
+
+        for (i, &value) in samples.iter().enumerate() {
+            match current.as_mut() {
+                None if value >= self.threshold => {
+                    current = Some(Peak {
+                        start: i,
+                        end: i + 1,
+                        max_index: i,
+                        max_value: value,
+                    });
+                }
+                None => {}
+                Some(peak) if value >= self.threshold - self.hysteresis => {
+                    peak.end = i + 1;
+                    if value > peak.max_value {
+                        peak.max_index = i;
+                        peak.max_value = value;
+                    }
+                }
+                Some(_) => {
+                    peaks.extend(current.take());
+                }
+            }
+        }
+        peaks.extend(current);
+
+        peaks.retain(|p| p.end - p.start >= self.min_width as usize);
+        peaks
+    }
+
+    /// `LIDAR SET <threshold> <hysteresis> <min width>` so the firmware
+    /// detects the same peaks.
+    pub fn parameters_message(&self) -> [MsgElem; 5] {
+        [
+            Code(LIDAR),
+            Code(SET),
+            F32(self.threshold),
+            F32(self.hysteresis),
+            U32(self.min_width),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start, end and max index of each peak found with the default settings:
+    /// threshold 100, release below 90, at least 3 samples wide.
+    fn detect(samples: &[f32]) -> Vec<(usize, usize, usize)> {
+        PeakDetector::new()
+            .detect(samples)
+            .iter()
+            .map(|p| (p.start, p.end, p.max_index))
+            .collect()
+    }
+
+    #[test]
+    fn single_peak() {
+        let samples = [0.0, 50.0, 100.0, 120.0, 150.0, 110.0, 95.0, 20.0, 0.0];
+        let peaks = PeakDetector::new().detect(&samples);
+        assert_eq!(detect(&samples), vec![(2, 7, 4)]);
+        assert_eq!(peaks[0].max_value, 150.0);
+    }
+
+    #[test]
+    fn dip_above_release_keeps_one_peak() {
+        let samples = [0.0, 110.0, 120.0, 95.0, 92.0, 130.0, 105.0, 0.0];
+        assert_eq!(detect(&samples), vec![(1, 7, 5)]);
+    }
+
+    #[test]
+    fn dip_below_release_splits_peaks() {
+        let samples = [0.0, 110.0, 120.0, 105.0, 85.0, 110.0, 130.0, 115.0, 0.0];
+        assert_eq!(detect(&samples), vec![(1, 4, 2), (5, 8, 6)]);
+    }
+
+    #[test]
+    fn peak_running_to_the_end() {
+        let samples = [0.0, 0.0, 120.0, 130.0, 140.0];
+        assert_eq!(detect(&samples), vec![(2, 5, 4)]);
+    }
+
+    #[test]
+    fn narrow_peaks_are_dropped() {
+        assert!(detect(&[0.0, 150.0, 140.0, 0.0, 0.0]).is_empty());
+        assert!(detect(&[]).is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+//! Lidar readings paired with the turntable angle and grouped into sweeps.
+
+use std::collections::VecDeque;
+
+use crate::odometry::wrap_angle;
+
+/// One reading, with the turntable angle in degrees at the time it arrived.
+#[derive(Clone, Copy)]
+pub struct ScanPoint {
+    pub angle: f32,
+    pub distance: f32,
+}
+
+pub struct LidarScan {
+    pub enabled: bool,
+    /// Completed sweeps, newest last.
+    pub sweeps: VecDeque<Vec<ScanPoint>>,
+    pub current: Vec<ScanPoint>,
+    /// How many completed sweeps to keep drawing.
+    pub persistence: usize,
+    /// Lidar units to metres. The default assumes millimetres.
+    pub distance_scale: f32,
+    /// Readings further than this (in metres) are dropped as misses.
+    pub max_range: f32,
+
+    /// Degrees turned since the sweep started, unwrapped through ±180°.
+    swept: f32,
+    last_angle: Option<f32>,
+    // Sign of the last angle change, to catch a back-and-forth sweep turning.
+    direction: f32,
+}
+
+impl LidarScan {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            sweeps: VecDeque::new(),
+            current: Vec::new(),
+            persistence: 4,
+            distance_scale: 0.001,
+            max_range: 2.0,
+            swept: 0.0,
+            last_angle: None,
+            direction: 0.0,
+        }
+    }
+
+    /// Add a reading. A sweep ends after a full turn or when the turntable
+    /// reverses direction.
+    pub fn record(&mut self, angle: f32, distance: f32) {
+        if !self.enabled {
+            return;
+        }
+
+        if let Some(last) = self.last_angle {
+            // Shortest way round, so wrapping from 180° to -180° isn't a
+            // reversal.
+            let delta = wrap_angle((angle - last).to_radians()).to_degrees();
+            if delta != 0.0 {
+                let reversed = self.direction != 0.0 && delta.signum() != self.direction;
+                self.direction = delta.signum();
+                self.swept += delta;
+                if reversed || self.swept.abs() >= 360.0 {
+                    self.finish_sweep();
+                }
+            }
+        }
+        self.last_angle = Some(angle);
+
+        let metres = distance * self.distance_scale;
+        if metres > 0.0 && metres <= self.max_range {
+            self.current.push(ScanPoint {
+                angle,
+                distance: metres,
+            });
+        }
+    }
+
+    fn finish_sweep(&mut self) {
+        self.swept = 0.0;
+        if self.current.is_empty() {
+            return;
+        }
+        self.sweeps.push_back(std::mem::take(&mut self.current));
+        while self.sweeps.len() > self.persistence {
+            self.sweeps.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.sweeps.clear();
+        self.current.clear();
+        self.swept = 0.0;
+        self.last_angle = None;
+        self.direction = 0.0;
+    }
+
+    /// Robot-frame position of a reading taken from `origin`.
+    pub fn to_robot(origin: [f32; 2], point: &ScanPoint) -> [f64; 2] {
+        let (sin, cos) = point.angle.to_radians().sin_cos();
+        [
+            (origin[0] + point.distance * cos) as f64,
+            (origin[1] + point.distance * sin) as f64,
+        ]
+    }
+}
@@ -0,0 +1,259 @@
+//! Magnetometer readings, hard/soft-iron calibration and heading.
+//!
+//! Calibration is done in the horizontal plane: the robot spins in place
+//! while readings are collected, and an ellipse is fitted to the X/Y points.
+//! Its centre is the hard-iron offset and the transform that turns it back
+//! into a circle is the soft-iron correction.
+
+use crate::least_squares::least_squares;
+use crate::odometry::wrap_angle;
+use crate::ring_buffer::RingBuffer;
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+pub struct MagCalibration {
+    /// Hard-iron offset subtracted from X/Y.
+    pub offset: [f32; 2],
+    /// Symmetric soft-iron matrix applied after the offset.
+    pub soft_iron: [[f32; 2]; 2],
+    /// Added to the magnetic heading so it lines up with odometry, radians.
+    pub heading_offset: f32,
+}
+
+impl MagCalibration {
+    pub fn new() -> Self {
+        Self {
+            offset: [0.0, 0.0],
+            soft_iron: [[1.0, 0.0], [0.0, 1.0]],
+            heading_offset: 0.0,
+        }
+    }
+
+    pub fn apply(&self, raw: [f32; 3]) -> [f32; 2] {
+        let x = raw[0] - self.offset[0];
+        let y = raw[1] - self.offset[1];
+        let m = &self.soft_iron;
+        [m[0][0] * x + m[0][1] * y, m[1][0] * x + m[1][1] * y]
+    }
+
+    pub fn heading(&self, raw: [f32; 3]) -> f32 {
+        let [x, y] = self.apply(raw);
+        wrap_angle(y.atan2(x) + self.heading_offset)
+    }
+}
+
+pub struct Magnetometer {
+    pub latest: Option<[f32; 3]>,
+    pub readings: RingBuffer<[f32; 3]>,
+    pub calibration: MagCalibration,
+
+    /// Readings collected during a calibration spin.
+    pub calibration_samples: Option<Vec<[f32; 3]>>,
+    /// Spin the robot with `DRIVE_BASE SET LEFT .. RIGHT ..` while calibrating.
+    pub spin: bool,
+    pub spin_speed: f32,
+    // Whether the current calibration started a spin that needs stopping.
+    spinning: bool,
+
+    /// Magnetic heading and odometry theta, radians.
+    pub heading_history: RingBuffer<[f64; 2]>,
+    pub status: String,
+}
+
+impl Magnetometer {
+    pub fn new() -> Self {
+        Self {
+            latest: None,
+            readings: RingBuffer::new(1024),
+            calibration: MagCalibration::new(),
+            calibration_samples: None,
+            spin: true,
+            spin_speed: 0.1,
+            spinning: false,
+            heading_history: RingBuffer::new(512),
+            status: String::new(),
+        }
+    }
+
+    pub fn record(&mut self, reading: [f32; 3], odometry_theta: Option<f32>) {
+        self.latest = Some(reading);
+        self.readings.push(reading);
+        if let Some(samples) = self.calibration_samples.as_mut() {
+            samples.push(reading);
+        }
+        if let Some(theta) = odometry_theta {
+            self.heading_history.push([
+                self.calibration.heading(reading) as f64,
+                wrap_angle(theta) as f64,
+            ]);
+        }
+    }
+
+    /// Start collecting. Returns the spin command if the robot should turn.
+    pub fn start_calibration(&mut self) -> Option<Vec<MsgElem>> {
+        self.calibration_samples = Some(Vec::new());
+        self.status = String::from("Collecting, rotate the robot at least one full turn.");
+        self.spinning = self.spin;
+        self.spin.then(|| spin_message(self.spin_speed))
+    }
+
+    /// Fit the collected readings. Returns the stop command if the calibration
+    /// started a spin.
+    pub fn finish_calibration(&mut self) -> Option<Vec<MsgElem>> {
+        let samples = self.calibration_samples.take()?;
+        match fit_ellipse(&samples) {
+            Some((offset, soft_iron)) => {
+                self.calibration.offset = offset;
+                self.calibration.soft_iron = soft_iron;
+                self.status = format!("Fitted ellipse to {} readings.", samples.len());
+            }
+            None => {
+                self.status = format!(
+                    "Couldn't fit an ellipse to {} readings, rotate further.",
+                    samples.len()
+                );
+            }
+        }
+        std::mem::take(&mut self.spinning).then(|| spin_message(0.0))
+    }
+
+    /// Set the heading offset so the current magnetic heading matches `theta`.
+    pub fn align_to(&mut self, theta: f32) {
+        if let Some(reading) = self.latest {
+            self.calibration.heading_offset = 0.0;
+            let heading = self.calibration.heading(reading);
+            self.calibration.heading_offset = wrap_angle(theta - heading);
+        }
+    }
+
+    /// `MAGNETOMETER SET <offset x> <offset y> <xx> <xy> <yy> <heading offset>`
+    pub fn calibration_message(&self) -> [MsgElem; 8] {
+        let c = &self.calibration;
+        [
+            Code(MAGNETOMETER),
+            Code(SET),
+            F32(c.offset[0]),
+            F32(c.offset[1]),
+            F32(c.soft_iron[0][0]),
+            F32(c.soft_iron[0][1]),
+            F32(c.soft_iron[1][1]),
+            F32(c.heading_offset),
+        ]
+    }
+}
+
+fn spin_message(speed: f32) -> Vec<MsgElem> {
+    vec![
+        Code(DRIVE_BASE),
+        Code(SET),
+        Code(LEFT),
+        F32(-speed),
+        Code(RIGHT),
+        F32(speed),
+    ]
+}
+
+/// Fit `Ax² + Bxy + Cy² + Dx + Ey = 1` to the X/Y readings and return the
+/// centre and the symmetric matrix mapping the ellipse onto a circle with
+/// the same area.
+fn fit_ellipse(samples: &[[f32; 3]]) -> Option<([f32; 2], [[f32; 2]; 2])> {
+    let rows: Vec<Vec<f64>> = samples
+        .iter()
+        .map(|s| {
+            let (x, y) = (s[0] as f64, s[1] as f64);
+            vec![x * x, x * y, y * y, x, y]
+        })
+        .collect();
+    let ones = vec![1.0; rows.len()];
+    let conic = least_squares(&rows, &ones)?;
+    let [a, b, c, d, e] = conic[..] else {
+        return None;
+    };
+
+    // Centre where the gradient vanishes: [2A B; B 2C] [x y]' = -[D E]'.
+    let det = 4.0 * a * c - b * b;
+    if det <= 0.0 {
+        // Not an ellipse.
+        return None;
+    }
+    let cx = (-2.0 * c * d + b * e) / det;
+    let cy = (b * d - 2.0 * a * e) / det;
+
+    // About the centre the ellipse is u' M u = k.
+    let k = 1.0 - (a * cx * cx + b * cx * cy + c * cy * cy + d * cx + e * cy);
+    if k <= 0.0 {
+        return None;
+    }
+    let m = [[a / k, 0.5 * b / k], [0.5 * b / k, c / k]];
+    let det_m = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+
+    // sqrt(M) maps the ellipse onto the unit circle; closed form for a 2x2
+    // positive definite matrix. Scaling by the geometric mean radius keeps
+    // the field magnitude.
+    let s = det_m.sqrt();
+    let t = (m[0][0] + m[1][1] + 2.0 * s).sqrt();
+    let radius = det_m.powf(-0.25);
+    let sqrt_m = |i: usize, j: usize| {
+        let identity = if i == j { s } else { 0.0 };
+        ((m[i][j] + identity) / t * radius) as f32
+    };
+
+    Some((
+        [cx as f32, cy as f32],
+        [[sqrt_m(0, 0), sqrt_m(0, 1)], [sqrt_m(1, 0), sqrt_m(1, 1)]],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn ellipse_maps_onto_circle() {
+        let (center, axes, rotation) = ([12.0, -5.0], [40.0, 20.0], 30f32.to_radians());
+        let samples: Vec<[f32; 3]> = (0..72)
+            .map(|i| {
+                let t = i as f32 * 2.0 * PI / 72.0;
+                let (u, v) = (axes[0] * t.cos(), axes[1] * t.sin());
+                let (sin, cos) = rotation.sin_cos();
+                [
+                    center[0] + u * cos - v * sin,
+                    center[1] + u * sin + v * cos,
+                    3.0,
+                ]
+            })
+            .collect();
+
+        let (offset, soft_iron) = fit_ellipse(&samples).unwrap();
+        assert!((offset[0] - center[0]).abs() < 1e-2 && (offset[1] - center[1]).abs() < 1e-2);
+
+        let calibration = MagCalibration {
+            offset,
+            soft_iron,
+            heading_offset: 0.0,
+        };
+        // Same area as the ellipse.
+        let radius = (axes[0] * axes[1]).sqrt();
+        for sample in &samples {
+            let [x, y] = calibration.apply(*sample);
+            assert!(
+                (x.hypot(y) - radius).abs() < 1e-2 * radius,
+                "{:?} -> ({}, {})",
+                sample,
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn degenerate_samples_have_no_fit() {
+        assert!(fit_ellipse(&[]).is_none());
+        assert!(fit_ellipse(&[[1.0, 2.0, 0.0]; 10]).is_none());
+
+        // Collinear readings, e.g. from a robot that never turned.
+        let line: Vec<[f32; 3]> = (0..20).map(|i| [i as f32, 2.0 * i as f32, 0.0]).collect();
+        assert!(fit_ellipse(&line).is_none());
+    }
+}
@@ -1,7 +1,27 @@
+mod arm_kinematics;
+mod arm_sequence;
+mod arm_view_3d;
+mod claw;
+mod drive_teleop;
+mod encoder;
+mod field_map;
+mod ir_beacon;
+mod least_squares;
+mod lidar_calibration;
+mod lidar_log;
+mod lidar_peaks;
+mod lidar_scan;
+mod magnetometer;
+mod odometry;
+mod odometry_calibration;
 mod ring_buffer;
+mod safety;
 
 mod serial;
 mod serial_protocol;
+mod tape_sensor;
+mod teleop;
+mod waypoints;
 
 use serialport::{available_ports, DataBits, SerialPortInfo, StopBits};
 use std::{
@@ -11,16 +31,41 @@ use std::{
 };
 
 use serial::MsgElem::*;
-use serial::{compare_messages, send_message, MessageBuffer, MsgElem};
+use serial::{compare_messages, f32_values, send_message, MessageBuffer, MsgElem};
 use serial_protocol::MessageCode::{self, *};
 
+use arm_kinematics::{ArmGeometry, ArmLimits, ElbowConfig, JointAngles, KeepOut};
+use arm_sequence::{ArmSequence, Interpolation, Keyframe};
+use arm_view_3d::{ArmScene, OrbitCamera};
+use claw::{Claw, GripState};
+use drive_teleop::{DriveMode, DriveTeleop};
+use encoder::Encoder;
+use field_map::FieldMapOverlay;
+use ir_beacon::IrBeacon;
+use lidar_calibration::{LidarCalibration, MAX_DEGREE};
+use lidar_log::LidarLog;
+use lidar_peaks::PeakDetector;
+use lidar_scan::LidarScan;
+use magnetometer::Magnetometer;
+use odometry::{
+    rates, run_stats, segment_ranges, segment_stats, set_pose_message, OdoPlotTool, PlotFrame, Pos,
+    PoseSetter, RobotConfig,
+};
+use odometry_calibration::{CalibrationStep, CalibrationWizard};
 use ring_buffer::RingBuffer;
+use safety::{LinkState, Safety};
+use tape_sensor::TapeSensor;
+use teleop::{Teleop, GAMEPAD_AXES, GAMEPAD_BUTTONS};
+use waypoints::WaypointPlan;
 
 use eframe::{
     egui::{self, Color32},
     glow::CONTEXT_FLAG_ROBUST_ACCESS_BIT,
 };
-use egui_plot::{Legend, Line, Plot, PlotPoint, PlotPoints};
+use egui_plot::{
+    Arrows, Bar, BarChart, HLine, Legend, Line, LineStyle, MarkerShape, Plot, PlotPoint,
+    PlotPoints, Points, Polygon, Text,
+};
 
 fn main() -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions::default();
@@ -36,8 +81,13 @@ fn main() -> Result<(), eframe::Error> {
 enum View {
     PIDTuning,
     OdoTracking,
+    DriveTeleop,
     ArmControl,
     LidarTuning,
+    Magnetometer,
+    IrBeacon,
+    TapeSensor,
+    Encoder,
 }
 
 #[derive(PartialEq, Clone)]
@@ -55,11 +105,10 @@ fn pid_target_to_msg(target: PIDTarget) -> MessageCode {
     }
 }
 
-#[derive(Debug)]
-struct Pos {
-    x: f32,
-    y: f32,
-    theta: f32,
+/// Valid claw positions over the arm plot, used to shade it and as the
+/// starting points when clamping a target.
+fn arm_workspace(limits: &ArmLimits, geometry: &ArmGeometry, elbow: ElbowConfig) -> Vec<[f64; 2]> {
+    limits.workspace(geometry, elbow, [-1.0, -1.0], [18.0, 18.0], 0.5)
 }
 
 struct SerialInterfaceApp {
@@ -71,12 +120,31 @@ struct SerialInterfaceApp {
     //   d_output
     pid_histogram: RingBuffer<[f64; 5]>,
     position_histogram: Vec<Pos>,
-    position_plot_angle: f32,
+    position_plot_frame: PlotFrame,
+    position_plot_tool: OdoPlotTool,
     position_histogram_front: Vec<Pos>,
+    // Indices where a new path segment starts, i.e. after a pose reset.
+    position_segments: Vec<usize>,
+    start_new_segment: bool,
+    pose_setter: PoseSetter,
+    odo_calibration: CalibrationWizard,
+    robot_config: RobotConfig,
+    show_footprint: bool,
+    // Draw a faded footprint every this many samples, 0 to disable.
+    footprint_ghost_interval: usize,
+    field_map: FieldMapOverlay,
+    waypoints: WaypointPlan,
+    start_time: Instant,
     lidar_distance_histogram: RingBuffer<f32>,
     lidar_convolution_histogram: RingBuffer<f32>,
-    lidar_distance_log: Vec<f32>,
-    lidar_convolution_log: Vec<f32>,
+    lidar_log: LidarLog,
+    lidar_peaks: PeakDetector,
+    lidar_calibration: LidarCalibration,
+    magnetometer: Magnetometer,
+    ir_beacon: IrBeacon,
+    tape_sensor: TapeSensor,
+    encoder: Encoder,
+    lidar_scan: LidarScan,
     view: View,
 
     available_ports: Vec<SerialPortInfo>,
@@ -89,8 +157,30 @@ struct SerialInterfaceApp {
 
     arm_r: f32,
     arm_h: f32,
+    arm_geometry: ArmGeometry,
+    arm_elbow: ElbowConfig,
+    arm_limits: ArmLimits,
+    // Valid claw positions for shading, dropped when geometry or limits change.
+    arm_workspace: Option<Vec<[f64; 2]>>,
+    // Set when the last arm target had to be clamped to the workspace.
+    arm_warning: Option<String>,
+    // Joint angles reported by the arm, in radians.
+    shoulder_measured: Option<f64>,
+    elbow_measured: Option<f64>,
+    // Commanded minus measured shoulder and elbow angle.
+    arm_tracking_error: RingBuffer<[f64; 2]>,
+    show_arm_3d: bool,
+    arm_camera: OrbitCamera,
+    // Degrees per scroll step.
     ttbl_sensitivity: f32,
+    // Relative turntable move still to be sent, in degrees.
     ttbl_val: f32,
+    // Commanded turntable angle in degrees: the sum of every relative delta
+    // sent, reset by absolute commands.
+    ttbl_angle: f32,
+    ttbl_measured_angle: Option<f32>,
+    claw: Claw,
+    arm_sequence: ArmSequence,
 
     serial_buffer: [u8; 1024],
     serial_buffer_index: usize,
@@ -105,6 +195,9 @@ struct SerialInterfaceApp {
 
     base_speed: f32,
     tape_following: bool,
+    teleop: Teleop,
+    drive_teleop: DriveTeleop,
+    safety: Safety,
 }
 
 impl SerialInterfaceApp {
@@ -117,12 +210,29 @@ impl SerialInterfaceApp {
         Self {
             pid_histogram: RingBuffer::new(128),
             position_histogram: Vec::new(),
-            position_plot_angle: 0.0,
+            position_plot_frame: PlotFrame::new(),
+            position_plot_tool: OdoPlotTool::Pan,
             position_histogram_front: Vec::new(),
+            position_segments: Vec::new(),
+            start_new_segment: false,
+            pose_setter: PoseSetter::new(),
+            odo_calibration: CalibrationWizard::new(),
+            robot_config: RobotConfig::new(),
+            show_footprint: true,
+            footprint_ghost_interval: 0,
+            field_map: FieldMapOverlay::new(),
+            waypoints: WaypointPlan::new(),
+            start_time: Instant::now(),
             lidar_distance_histogram: RingBuffer::new(1024),
             lidar_convolution_histogram: RingBuffer::new(1024),
-            lidar_distance_log: Vec::new(),
-            lidar_convolution_log: Vec::new(),
+            lidar_log: LidarLog::new(),
+            lidar_peaks: PeakDetector::new(),
+            lidar_calibration: LidarCalibration::new(),
+            magnetometer: Magnetometer::new(),
+            ir_beacon: IrBeacon::new(),
+            tape_sensor: TapeSensor::new(),
+            encoder: Encoder::new(),
+            lidar_scan: LidarScan::new(),
             view: View::PIDTuning,
             available_ports,
             port_name: String::new(),
@@ -134,8 +244,22 @@ impl SerialInterfaceApp {
 
             arm_r: 10.0,
             arm_h: 10.0,
+            arm_geometry: ArmGeometry::new(),
+            arm_elbow: ElbowConfig::Up,
+            arm_limits: ArmLimits::new(),
+            arm_workspace: None,
+            arm_warning: None,
+            shoulder_measured: None,
+            elbow_measured: None,
+            arm_tracking_error: RingBuffer::new(512),
+            show_arm_3d: false,
+            arm_camera: OrbitCamera::new(),
             ttbl_sensitivity: 1.0,
             ttbl_val: 0.0,
+            ttbl_angle: 0.0,
+            ttbl_measured_angle: None,
+            claw: Claw::new(),
+            arm_sequence: ArmSequence::new(),
 
             serial_buffer: [0; 1024],
             serial_buffer_index: 0,
@@ -150,8 +274,84 @@ impl SerialInterfaceApp {
 
             base_speed: 0.0,
             tape_following: false,
+            teleop: Teleop::new(),
+            drive_teleop: DriveTeleop::new(),
+            safety: Safety::new(),
+        }
+    }
+
+    /// Halt everything and stop streaming motion commands until released.
+    fn emergency_stop(&mut self) {
+        println!("Emergency stop!");
+        let messages = self.safety.estop();
+
+        self.teleop.enabled = false;
+        self.arm_sequence.abort();
+        self.odo_calibration.abort();
+        self.tape_following = false;
+        self.base_speed = 0.0;
+        self.ttbl_val = 0.0;
+
+        if let Some(port) = self.port.as_mut() {
+            for message in &messages {
+                send_message(port, message);
+            }
+        }
+    }
+
+    /// Send a command unless the E-stop is latched and it would move the
+    /// robot. Stop commands and the heartbeat use `send_message` directly.
+    /// Returns whether the command went out.
+    fn send_command(&mut self, message: &[MsgElem]) -> bool {
+        if !self.safety.allows(message) {
+            return false;
+        }
+        match self.port.as_mut() {
+            Some(port) => send_message(port, message),
+            None => false,
+        }
+    }
+
+    /// Aim the arm at `(r, h)`, or at the closest valid point if that breaks
+    /// the limits.
+    fn set_arm_target(&mut self, r: f64, h: f64) {
+        match self
+            .arm_limits
+            .check(&self.arm_geometry, r, h, self.arm_elbow)
+        {
+            Ok(_) => {
+                self.arm_r = r as f32;
+                self.arm_h = h as f32;
+                self.arm_warning = None;
+            }
+            Err(violation) => {
+                let workspace = self.arm_workspace.get_or_insert_with(|| {
+                    arm_workspace(&self.arm_limits, &self.arm_geometry, self.arm_elbow)
+                });
+                if let Some([r, h]) =
+                    self.arm_limits
+                        .clamp(&self.arm_geometry, r, h, self.arm_elbow, workspace)
+                {
+                    self.arm_r = r as f32;
+                    self.arm_h = h as f32;
+                }
+                self.arm_warning = Some(format!(
+                    "Target invalid ({:?}), clamped to ({:.2}, {:.2}).",
+                    violation, self.arm_r, self.arm_h
+                ));
+            }
         }
     }
+
+    /// Move the turntable `degrees` from its commanded angle with a relative
+    /// `TTBL SET <degrees>`.
+    fn turntable_step(&mut self, degrees: f32) {
+        if self.safety.estopped {
+            return;
+        }
+        self.ttbl_angle += degrees;
+        self.send_command(&[Code(TTBL), Code(SET), F32(degrees)]);
+    }
 }
 
 const ESP_UPDATE_MESSAGE: [MsgElem; 10] = [
@@ -169,7 +369,23 @@ const ESP_UPDATE_MESSAGE: [MsgElem; 10] = [
 
 const LIDAR_MESSAGE: [MsgElem; 3] = [Code(LIDAR), F32(0.0), F32(0.0)];
 
-const LIDAR_LOG_MESSAGE: [MsgElem; 4] = [Code(LIDAR), Code(ALL), F32(0.0), F32(0.0)];
+const TTBL_ANGLE_MESSAGE: [MsgElem; 3] = [Code(TTBL), Code(ANGLE), F32(0.0)];
+
+const CLAW_MESSAGE: [MsgElem; 3] = [Code(CLAW), F32(0.0), F32(0.0)];
+
+const SHOULDER_ANGLE_MESSAGE: [MsgElem; 3] = [Code(SHOULDER), Code(ANGLE), F32(0.0)];
+
+const ELBOW_ANGLE_MESSAGE: [MsgElem; 3] = [Code(ELBOW), Code(ANGLE), F32(0.0)];
+
+const MAGNETOMETER_MESSAGE: [MsgElem; 4] = [Code(MAGNETOMETER), F32(0.0), F32(0.0), F32(0.0)];
+
+const ENCODER_ANGLE_MESSAGE: [MsgElem; 3] = [Code(ENCODER_MOTOR), Code(ANGLE), F32(0.0)];
+
+const ENCODER_VELOCITY_MESSAGE: [MsgElem; 3] = [Code(ENCODER_MOTOR), Code(VELOCITY), F32(0.0)];
+
+const HEARTBEAT_MESSAGE: [MsgElem; 2] = [Code(NONE), U32(0)];
+
+const DRIVE_VELOCITY_MESSAGE: [MsgElem; 4] = [Code(DRIVE_BASE), Code(VELOCITY), F32(0.0), F32(0.0)];
 
 impl eframe::App for SerialInterfaceApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
@@ -212,15 +428,25 @@ impl eframe::App for SerialInterfaceApp {
                     x: v[0],
                     y: v[1],
                     theta: v[2],
+                    t: self.start_time.elapsed().as_secs_f32(),
                 };
                 // println!("new odo elem {:?}", new_elem);
+                let front_elem = self.robot_config.front_point(&new_elem);
+                if let Some(message) = self.odo_calibration.update(&new_elem) {
+                    println!("Calibration run finished, stopping drive base.");
+                    if let Some(port) = self.port.as_mut() {
+                        send_message(port, &message);
+                    }
+                }
+                if self.start_new_segment {
+                    self.start_new_segment = false;
+                    self.position_segments.push(self.position_histogram.len());
+                }
+                self.waypoints
+                    .record([new_elem.x as f64, new_elem.y as f64]);
                 self.position_histogram.push(new_elem);
 
-                let new_elem = Pos {
-                    x: v[0] + 0.235 * v[2].cos(),
-                    y: v[1] + 0.235 * v[2].sin(),
-                    theta: v[2],
-                };
+                let new_elem = front_elem;
 
                 // println!("new front elem {:?}", new_elem);
 
@@ -232,22 +458,178 @@ impl eframe::App for SerialInterfaceApp {
             } else if compare_messages(&message, &LIDAR_MESSAGE) {
                 if let F32(distance) = message[1] {
                     self.lidar_distance_histogram.push(distance);
+                    self.lidar_calibration.record(distance);
+                    self.lidar_scan.record(
+                        self.ttbl_measured_angle.unwrap_or(self.ttbl_angle),
+                        distance,
+                    );
                 }
 
                 if let F32(convolution) = message[2] {
                     self.lidar_convolution_histogram.push(convolution);
                 }
-            } else if compare_messages(&message, &LIDAR_LOG_MESSAGE) {
-                if let F32(distance) = message[2] {
-                    self.lidar_distance_log.push(distance);
+            } else if compare_messages(&message, &TTBL_ANGLE_MESSAGE) {
+                if let F32(angle) = message[2] {
+                    self.ttbl_measured_angle = Some(angle);
+                }
+            } else if compare_messages(&message, &CLAW_MESSAGE) {
+                if let (F32(angle), F32(current)) = (&message[1], &message[2]) {
+                    self.claw.record(*angle, *current);
+                }
+            } else if compare_messages(&message, &SHOULDER_ANGLE_MESSAGE)
+                || compare_messages(&message, &ELBOW_ANGLE_MESSAGE)
+            {
+                if let F32(angle) = message[2] {
+                    if message[0] == Code(SHOULDER) {
+                        self.shoulder_measured = Some(angle as f64);
+                    } else {
+                        self.elbow_measured = Some(angle as f64);
+                    }
                 }
 
-                if let F32(convolution) = message[2] {
-                    self.lidar_distance_log.push(convolution);
+                if let (Some(shoulder), Some(elbow), Ok(commanded)) = (
+                    self.shoulder_measured,
+                    self.elbow_measured,
+                    self.arm_geometry
+                        .inverse(self.arm_r as f64, self.arm_h as f64, self.arm_elbow),
+                ) {
+                    self.arm_tracking_error
+                        .push([commanded.shoulder - shoulder, commanded.elbow - elbow]);
+                }
+            } else if compare_messages(&message, &DRIVE_VELOCITY_MESSAGE) {
+                if let (F32(left), F32(right)) = (&message[2], &message[3]) {
+                    self.drive_teleop.record(*left, *right);
+                }
+            } else if compare_messages(&message, &MAGNETOMETER_MESSAGE) {
+                if let (F32(x), F32(y), F32(z)) = (&message[1], &message[2], &message[3]) {
+                    self.magnetometer.record(
+                        [*x, *y, *z],
+                        self.position_histogram.last().map(|p| p.theta),
+                    );
+                }
+            } else if message.len() > 1 && message[0] == Code(IR_BEACON) {
+                if let Some(strengths) = f32_values(&message[1..]) {
+                    self.ir_beacon.record(strengths);
+                }
+            } else if message.len() > 2 && message[0] == Code(TAPE_SENSOR) {
+                match (&message[1], f32_values(&message[2..])) {
+                    (Code(RAW), Some(values)) => self.tape_sensor.record_raw(values),
+                    (Code(CONVERTED), Some(values)) => self.tape_sensor.record_converted(values),
+                    _ => {}
+                }
+            } else if compare_messages(&message, &ENCODER_ANGLE_MESSAGE) {
+                if let F32(angle) = message[2] {
+                    self.encoder.record_angle(angle);
+                }
+            } else if compare_messages(&message, &ENCODER_VELOCITY_MESSAGE) {
+                if let F32(velocity) = message[2] {
+                    self.encoder.record_velocity(velocity);
+                }
+            } else if compare_messages(&message, &HEARTBEAT_MESSAGE) {
+                if let U32(sequence) = message[1] {
+                    self.safety.ack(sequence);
                 }
+            } else if message.starts_with(&[Code(LIDAR), Code(ALL)])
+                && !self.lidar_log.decode(&message[2..])
+            {
+                println!("Malformed lidar log message: {:?}", message);
+            }
+        }
+
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.emergency_stop();
+        }
+
+        if let Some(message) = self.safety.heartbeat()
+            && let Some(port) = self.port.as_mut()
+        {
+            send_message(port, &message);
+        }
+
+        if let Some(message) = self.odo_calibration.tick(Instant::now()) {
+            println!("Calibration run timed out, stopping drive base.");
+            if let Some(port) = self.port.as_mut() {
+                send_message(port, &message);
+            }
+        }
+
+        if let Some(command) = self.teleop.update(ctx).filter(|_| !self.safety.estopped) {
+            let mut messages = Vec::new();
+
+            if let Some([left, right]) = command.drive {
+                messages.push(vec![
+                    Code(DRIVE_BASE),
+                    Code(SET),
+                    Code(LEFT),
+                    F32(left),
+                    Code(RIGHT),
+                    F32(right),
+                ]);
+            }
+
+            if command.arm != [0.0, 0.0] {
+                let r = self.arm_r + command.arm[0];
+                let h = self.arm_h + command.arm[1];
+                // Stop at the edge of the workspace rather than clamping.
+                if self
+                    .arm_limits
+                    .check(&self.arm_geometry, r as f64, h as f64, self.arm_elbow)
+                    .is_ok()
+                {
+                    self.arm_r = r;
+                    self.arm_h = h;
+                    messages.push(vec![Code(ARM), Code(SET), F32(r), F32(h)]);
+                }
+            }
+
+            if command.turntable != 0.0 {
+                self.turntable_step(command.turntable);
+            }
+
+            if command.claw_toggle {
+                messages.push(self.claw.toggle_message().to_vec());
+            }
+
+            for message in &messages {
+                self.send_command(message);
             }
         }
 
+        egui::TopBottomPanel::top("Emergency Stop").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if self.safety.estopped {
+                    ui.label(
+                        egui::RichText::new("STOPPED")
+                            .size(24.0)
+                            .strong()
+                            .color(Color32::RED),
+                    );
+                    if ui.button("Release E-Stop").clicked() {
+                        self.safety.release();
+                    }
+                    if self.safety.refused > 0 {
+                        ui.colored_label(
+                            Color32::YELLOW,
+                            format!("Refused {} motion commands", self.safety.refused),
+                        );
+                    }
+                } else if ui
+                    .add(
+                        egui::Button::new(
+                            egui::RichText::new("EMERGENCY STOP (Esc)")
+                                .size(24.0)
+                                .strong()
+                                .color(Color32::WHITE),
+                        )
+                        .fill(Color32::from_rgb(200, 0, 0)),
+                    )
+                    .clicked()
+                {
+                    self.emergency_stop();
+                }
+            });
+        });
+
         egui::SidePanel::left("Serial Connection")
             .resizable(false)
             .show(ctx, |ui| {
@@ -298,16 +680,144 @@ impl eframe::App for SerialInterfaceApp {
                         None => String::from_utf8("None".into()).unwrap(),
                     },
                 ));
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.safety.heartbeat_enabled, "Heartbeat every");
+                    ui.add(
+                        egui::DragValue::new(&mut self.safety.heartbeat_interval_ms)
+                            .range(20..=2000)
+                            .suffix(" ms"),
+                    );
+                });
+                let (text, color) = match self.safety.link_state() {
+                    LinkState::Disabled => (String::from("Heartbeat off"), Color32::GRAY),
+                    LinkState::Waiting => (
+                        format!("Sent #{}, no echo yet", self.safety.sequence),
+                        Color32::YELLOW,
+                    ),
+                    LinkState::Ok => (
+                        format!("Link OK, sent #{}", self.safety.sequence),
+                        Color32::GREEN,
+                    ),
+                    LinkState::Lost => (
+                        format!("Link lost, sent #{}", self.safety.sequence),
+                        Color32::RED,
+                    ),
+                };
+                ui.colored_label(color, text);
+                if let Some((sequence, at)) = self.safety.last_ack {
+                    ui.label(format!(
+                        "Last echo #{} {:.1} s ago",
+                        sequence,
+                        at.elapsed().as_secs_f32()
+                    ));
+                }
+
+                ui.separator();
+
+                ui.collapsing("Teleoperation", |ui| {
+                    ui.checkbox(&mut self.teleop.enabled, "Enable keyboard / gamepad");
+                    ui.label(&self.teleop.gamepad_status);
+
+                    egui::Grid::new("Teleop Settings").show(ui, |ui| {
+                        ui.label("Dead zone");
+                        ui.add(egui::Slider::new(&mut self.teleop.dead_zone, 0.0..=0.9));
+                        ui.end_row();
+                        ui.label("Drive speed");
+                        ui.add(egui::DragValue::new(&mut self.teleop.drive_speed).speed(0.01));
+                        ui.end_row();
+                        ui.label("Turn speed");
+                        ui.add(egui::DragValue::new(&mut self.teleop.turn_speed).speed(0.01));
+                        ui.end_row();
+                        ui.label("Arm rate");
+                        ui.add(
+                            egui::DragValue::new(&mut self.teleop.arm_rate)
+                                .speed(0.1)
+                                .suffix(" /s"),
+                        );
+                        ui.end_row();
+                        ui.label("Turntable rate");
+                        ui.add(
+                            egui::DragValue::new(&mut self.teleop.turntable_rate)
+                                .speed(1.0)
+                                .suffix(" °/s"),
+                        );
+                        ui.end_row();
+                        ui.label("Send interval");
+                        ui.add(
+                            egui::DragValue::new(&mut self.teleop.send_interval_ms)
+                                .range(10..=1000)
+                                .suffix(" ms"),
+                        );
+                        ui.end_row();
+                    });
+
+                    ui.collapsing("Key Bindings", |ui| {
+                        egui::Grid::new("Key Bindings").show(ui, |ui| {
+                            for (name, key) in self.teleop.keys.entries() {
+                                ui.label(name);
+                                egui::ComboBox::from_id_salt(name)
+                                    .selected_text(key.name())
+                                    .show_ui(ui, |ui| {
+                                        for k in egui::Key::ALL {
+                                            ui.selectable_value(key, *k, k.name());
+                                        }
+                                    });
+                                ui.end_row();
+                            }
+                        });
+                    });
+
+                    ui.collapsing("Gamepad Mapping", |ui| {
+                        egui::Grid::new("Gamepad Mapping").show(ui, |ui| {
+                            for (name, axis) in self.teleop.gamepad.axes() {
+                                ui.label(name);
+                                egui::ComboBox::from_id_salt(("axis", name))
+                                    .selected_text(format!("{:?}", axis))
+                                    .show_ui(ui, |ui| {
+                                        for a in GAMEPAD_AXES {
+                                            ui.selectable_value(axis, a, format!("{:?}", a));
+                                        }
+                                    });
+                                ui.end_row();
+                            }
+                            for (name, button) in self.teleop.gamepad.buttons() {
+                                ui.label(name);
+                                egui::ComboBox::from_id_salt(("button", name))
+                                    .selected_text(format!("{:?}", button))
+                                    .show_ui(ui, |ui| {
+                                        for b in GAMEPAD_BUTTONS {
+                                            ui.selectable_value(button, b, format!("{:?}", b));
+                                        }
+                                    });
+                                ui.end_row();
+                            }
+                        });
+                    });
+                });
             });
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.horizontal_top(|ui| {
+            ui.horizontal_wrapped(|ui| {
                 ui.radio_value(&mut self.view, View::PIDTuning, "PID");
                 ui.radio_value(&mut self.view, View::OdoTracking, "Odometry");
+                ui.radio_value(&mut self.view, View::DriveTeleop, "Drive");
                 ui.radio_value(&mut self.view, View::ArmControl, "Arm Control");
                 ui.radio_value(&mut self.view, View::LidarTuning, "Lidar Tuning");
+                ui.radio_value(&mut self.view, View::Magnetometer, "Magnetometer");
+                ui.radio_value(&mut self.view, View::IrBeacon, "IR Beacon");
+                ui.radio_value(&mut self.view, View::TapeSensor, "Tape Sensors");
+                ui.radio_value(&mut self.view, View::Encoder, "Encoder");
             });
 
+            // Playback only advances while the arm view is shown, so hold it
+            // rather than jumping ahead on return.
+            if self.view != View::ArmControl {
+                self.arm_sequence.pause();
+            }
+
             match self.view {
                 View::PIDTuning => {
                     let error: Vec<PlotPoint> = (0..self.pid_histogram.len())
@@ -423,7 +933,10 @@ impl eframe::App for SerialInterfaceApp {
                         );
                     });
 
-                    if ui.button("Send PID Vals").clicked() {
+                    if ui
+                        .add_enabled(!self.safety.estopped, egui::Button::new("Send PID Vals"))
+                        .clicked()
+                    {
                         let message = [
                             Code(PID),
                             Code(SET),
@@ -435,9 +948,7 @@ impl eframe::App for SerialInterfaceApp {
                             F32(self.max_ce),
                         ];
 
-                        if let Some(port) = self.port.as_mut() {
-                            send_message(port, &message);
-                        }
+                        self.send_command(&message);
                     }
 
                     ui.horizontal(|ui| {
@@ -452,7 +963,13 @@ impl eframe::App for SerialInterfaceApp {
                         self.tape_following = !self.tape_following;
                     }
 
-                    if ui.button("Send Speed and Tape Following").clicked() {
+                    if ui
+                        .add_enabled(
+                            !self.safety.estopped,
+                            egui::Button::new("Send Speed and Tape Following"),
+                        )
+                        .clicked()
+                    {
                         // Send tape following message.
                         println!("Sending speed and tape following message...");
                         let message = vec![
@@ -462,33 +979,148 @@ impl eframe::App for SerialInterfaceApp {
                             U32(self.tape_following as u32),
                         ];
 
-                        if let Some(port) = self.port.as_mut() {
-                            send_message(port, &message);
-                        }
+                        self.send_command(&message);
                     }
                 }
                 View::OdoTracking => {
-                    let pos1: PlotPoints = (0..self.position_histogram.len())
-                        // TODO: add plot angle as rotation matrix.
-                        .map(|i| {
-                            [
-                                self.position_histogram[i].x as f64,
-                                self.position_histogram[i].y as f64,
-                            ]
+                    let transform = self
+                        .position_plot_frame
+                        .transform(self.position_histogram.first());
+
+                    let segments =
+                        segment_ranges(self.position_histogram.len(), &self.position_segments);
+
+                    let back_lines: Vec<Line> = segments
+                        .iter()
+                        .map(|range| {
+                            let points: PlotPoints = self.position_histogram[range.clone()]
+                                .iter()
+                                .map(|p| transform.apply(p.x as f64, p.y as f64))
+                                .collect();
+                            Line::new("Back Position", points).color(Color32::LIGHT_BLUE)
                         })
                         .collect();
 
-                    let pos2: PlotPoints = (0..self.position_histogram_front.len())
-                        .map(|i| {
-                            [
-                                self.position_histogram_front[i].x as f64,
-                                self.position_histogram_front[i].y as f64,
-                            ]
+                    let front_lines: Vec<Line> = segments
+                        .iter()
+                        .map(|range| {
+                            let points: PlotPoints = self.position_histogram_front[range.clone()]
+                                .iter()
+                                .map(|p| transform.apply(p.x as f64, p.y as f64))
+                                .collect();
+                            Line::new("Front Position", points).color(Color32::LIGHT_RED)
                         })
                         .collect();
 
-                    let line1 = Line::new("Back Position", pos1);
-                    let line2 = Line::new("Front Position", pos2);
+                    let to_plot = |p: [f64; 2]| transform.apply(p[0], p[1]);
+
+                    let map_to_plot = |p: [f64; 2]| to_plot(self.field_map.alignment.apply(p));
+
+                    let mut map_lines: Vec<(&str, Vec<[f64; 2]>, Color32, f32)> = Vec::new();
+                    let mut map_beacons: Vec<([f64; 2], String)> = Vec::new();
+                    if let (true, Some(map)) = (self.field_map.show, &self.field_map.map) {
+                        for segment in &map.lines {
+                            map_lines.push((
+                                "Field Lines",
+                                segment.iter().map(|p| map_to_plot(*p)).collect(),
+                                Color32::GRAY,
+                                1.0,
+                            ));
+                        }
+                        for path in &map.tape {
+                            map_lines.push((
+                                "Tape",
+                                path.iter().map(|p| map_to_plot(*p)).collect(),
+                                Color32::from_rgb(60, 90, 200),
+                                4.0,
+                            ));
+                        }
+                        for wall in &map.walls {
+                            map_lines.push((
+                                "Walls",
+                                wall.iter().map(|p| map_to_plot(*p)).collect(),
+                                Color32::from_rgb(150, 110, 70),
+                                3.0,
+                            ));
+                        }
+                        for beacon in &map.beacons {
+                            map_beacons.push((map_to_plot(beacon.position), beacon.name.clone()));
+                        }
+                    }
+
+                    let planned_path: Vec<[f64; 2]> =
+                        self.waypoints.points.iter().map(|p| to_plot(*p)).collect();
+
+                    let pose_preview = if self.position_plot_tool == OdoPlotTool::SetPose {
+                        let pos = self.pose_setter.pos();
+                        let footprint: Vec<[f64; 2]> = self
+                            .robot_config
+                            .footprint(&pos)
+                            .into_iter()
+                            .map(to_plot)
+                            .collect();
+                        let origin = to_plot([pos.x as f64, pos.y as f64]);
+                        let tip = to_plot(
+                            self.robot_config
+                                .to_world(&pos, [self.robot_config.front_offset, 0.0]),
+                        );
+                        Some((footprint, origin, tip))
+                    } else {
+                        None
+                    };
+
+                    let mut ghosts: Vec<Vec<[f64; 2]>> = Vec::new();
+                    if self.footprint_ghost_interval > 0 {
+                        for pos in self
+                            .position_histogram
+                            .iter()
+                            .step_by(self.footprint_ghost_interval)
+                        {
+                            ghosts.push(
+                                self.robot_config
+                                    .footprint(pos)
+                                    .into_iter()
+                                    .map(to_plot)
+                                    .collect(),
+                            );
+                        }
+                    }
+
+                    let robot = self.position_histogram.last().map(|pos| {
+                        let footprint: Vec<[f64; 2]> = self
+                            .robot_config
+                            .footprint(pos)
+                            .into_iter()
+                            .map(to_plot)
+                            .collect();
+                        let origin = to_plot([pos.x as f64, pos.y as f64]);
+                        let tip = to_plot(
+                            self.robot_config
+                                .to_world(pos, [self.robot_config.front_offset, 0.0]),
+                        );
+                        let arm = to_plot(
+                            self.robot_config
+                                .to_world(pos, self.robot_config.arm_position),
+                        );
+                        (footprint, origin, tip, arm)
+                    });
+
+                    let line_length = self.ir_beacon.line_length as f64;
+                    let bearing_fixes: Vec<Vec<[f64; 2]>> = if self.ir_beacon.show_on_map {
+                        self.ir_beacon
+                            .fixes
+                            .iter()
+                            .map(|fix| fix.points(line_length).into_iter().map(to_plot).collect())
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+                    let bearing_line: Option<Vec<[f64; 2]>> = self
+                        .position_histogram
+                        .last()
+                        .and_then(|pos| self.ir_beacon.line_of_bearing(pos))
+                        .filter(|_| self.ir_beacon.show_on_map)
+                        .map(|line| line.points(line_length).into_iter().map(to_plot).collect());
 
                     let plot_height = ui.available_height() * 0.8;
 
@@ -496,154 +1128,1414 @@ impl eframe::App for SerialInterfaceApp {
                         // .view_aspect(1.0)
                         .data_aspect(1.0)
                         .height(plot_height)
+                        .allow_drag(self.position_plot_tool == OdoPlotTool::Pan)
                         .legend(egui_plot::Legend::default())
                         .show(ui, |plot_ui| {
-                            plot_ui.line(line1);
-                            plot_ui.line(line2);
+                            for (name, points, color, width) in map_lines {
+                                plot_ui.line(Line::new(name, points).color(color).width(width));
+                            }
+                            for (position, name) in map_beacons {
+                                plot_ui.points(
+                                    Points::new("Beacons", vec![position])
+                                        .radius(5.0)
+                                        .color(Color32::RED),
+                                );
+                                plot_ui.text(Text::new(
+                                    "Beacons",
+                                    PlotPoint::new(position[0], position[1] + 0.05),
+                                    name,
+                                ));
+                            }
+
+                            for line in back_lines {
+                                plot_ui.line(line);
+                            }
+                            for line in front_lines {
+                                plot_ui.line(line);
+                            }
+
+                            if !planned_path.is_empty() {
+                                plot_ui.line(
+                                    Line::new("Planned Path", planned_path.clone())
+                                        .color(Color32::LIGHT_GREEN)
+                                        .style(LineStyle::dashed_loose()),
+                                );
+                                plot_ui.points(
+                                    Points::new("Planned Path", planned_path.clone())
+                                        .radius(4.0)
+                                        .color(Color32::LIGHT_GREEN),
+                                );
+                                for (i, p) in planned_path.iter().enumerate() {
+                                    plot_ui.text(Text::new(
+                                        "Planned Path",
+                                        PlotPoint::new(p[0], p[1] + 0.05),
+                                        format!("{}", i),
+                                    ));
+                                }
+                            }
+
+                            if self.show_footprint {
+                                for ghost in ghosts {
+                                    plot_ui.line(
+                                        Line::new("Footprint History", ghost).color(
+                                            Color32::from_rgba_unmultiplied(200, 200, 200, 40),
+                                        ),
+                                    );
+                                }
+
+                                if let Some((footprint, origin, tip, arm)) = robot {
+                                    plot_ui.polygon(Polygon::new("Robot", footprint).fill_color(
+                                        Color32::from_rgba_unmultiplied(255, 200, 0, 40),
+                                    ));
+                                    plot_ui.arrows(
+                                        Arrows::new("Heading", vec![origin], vec![tip])
+                                            .color(Color32::YELLOW),
+                                    );
+                                    plot_ui.points(
+                                        Points::new("Arm", vec![arm])
+                                            .radius(4.0)
+                                            .color(Color32::LIGHT_BLUE),
+                                    );
+                                }
+                            }
+
+                            for fix in bearing_fixes {
+                                plot_ui.line(
+                                    Line::new("Beacon Fixes", fix)
+                                        .color(Color32::from_rgba_unmultiplied(255, 80, 80, 120)),
+                                );
+                            }
+                            if let Some(line) = bearing_line {
+                                plot_ui.line(
+                                    Line::new("Beacon Bearing", line)
+                                        .color(Color32::RED)
+                                        .style(LineStyle::dashed_dense()),
+                                );
+                            }
+
+                            if let Some((footprint, origin, tip)) = pose_preview {
+                                plot_ui.line(
+                                    Line::new("New Pose", footprint)
+                                        .color(Color32::WHITE)
+                                        .style(LineStyle::dashed_dense()),
+                                );
+                                plot_ui.arrows(
+                                    Arrows::new("New Pose", vec![origin], vec![tip])
+                                        .color(Color32::WHITE),
+                                );
+                            }
+
+                            if let Some(mouse_pos) = plot_ui.pointer_coordinate() {
+                                if self.position_plot_tool == OdoPlotTool::SetPose {
+                                    let p = transform.invert(mouse_pos.x, mouse_pos.y);
+                                    let response = plot_ui.response();
+                                    if response.drag_started() {
+                                        self.pose_setter.drag_start = Some(p);
+                                    }
+                                    if response.dragged() || response.drag_stopped() {
+                                        self.pose_setter.drag_to(p);
+                                    }
+                                    if response.drag_stopped() {
+                                        self.pose_setter.drag_start = None;
+                                    }
+                                }
+
+                                if plot_ui.response().clicked() {
+                                    match self.position_plot_tool {
+                                        OdoPlotTool::Pan => {}
+                                        OdoPlotTool::SetOrigin => {
+                                            self.position_plot_frame.origin =
+                                                transform.invert(mouse_pos.x, mouse_pos.y);
+                                            self.position_plot_tool = OdoPlotTool::Pan;
+                                        }
+                                        OdoPlotTool::AlignMap => {
+                                            if self.field_map.add_pick(
+                                                transform.invert(mouse_pos.x, mouse_pos.y),
+                                            ) {
+                                                self.position_plot_tool = OdoPlotTool::Pan;
+                                            }
+                                        }
+                                        OdoPlotTool::AddWaypoint => {
+                                            self.waypoints
+                                                .points
+                                                .push(transform.invert(mouse_pos.x, mouse_pos.y));
+                                        }
+                                        OdoPlotTool::SetPose => {
+                                            let p = transform.invert(mouse_pos.x, mouse_pos.y);
+                                            self.pose_setter.x = p[0] as f32;
+                                            self.pose_setter.y = p[1] as f32;
+                                        }
+                                    }
+                                }
+                            }
                         });
 
                     if ui.button("Erase Path").clicked() {
                         self.position_histogram.clear();
                         self.position_histogram_front.clear();
+                        self.position_segments.clear();
                     }
 
                     ui.horizontal(|ui| {
-                        ui.label("Plot Angle");
+                        ui.label("Pose");
+                        ui.add(
+                            egui::DragValue::new(&mut self.pose_setter.x)
+                                .speed(0.005)
+                                .prefix("x: "),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut self.pose_setter.y)
+                                .speed(0.005)
+                                .prefix("y: "),
+                        );
                         ui.add(
-                            egui::DragValue::new(&mut self.position_plot_angle)
+                            egui::DragValue::new(&mut self.pose_setter.theta)
                                 .speed(0.5)
-                                .range(0.0..=360.0),
+                                .prefix("θ: ")
+                                .suffix("°"),
                         );
-                    });
-                }
-                View::ArmControl => {
-                    for event in ctx.input(|i| i.events.clone()) {
-                        if let egui::Event::MouseWheel {
-                            unit: _,
-                            delta,
-                            modifiers: _,
-                        } = event
-                        {
-                            self.ttbl_val += delta.y;
-                        }
-                    }
-
-                    const TTBL_DELAY: Duration = Duration::from_millis(50);
-
-                    const ARM_DELAY: Duration = Duration::from_millis(80);
-
-                    if self.last_ttb_msg.elapsed() >= TTBL_DELAY && self.ttbl_val != 0.0 {
-                        self.last_ttb_msg = Instant::now();
-                        let message = vec![
-                            Code(TTBL),
-                            Code(SET),
-                            F32(self.ttbl_val * self.ttbl_sensitivity),
-                        ];
 
-                        self.ttbl_val = 0.0;
+                        ui.selectable_value(
+                            &mut self.position_plot_tool,
+                            OdoPlotTool::SetPose,
+                            "Drag Pose on Plot",
+                        );
 
-                        if let Some(port) = self.port.as_mut() {
-                            send_message(port, &message);
+                        let mut send_pose = false;
+                        if ui.button("Set Pose").clicked() {
+                            send_pose = true;
+                        }
+                        if ui.button("Reset to Zero").clicked() {
+                            self.pose_setter = PoseSetter::new();
+                            send_pose = true;
                         }
-                    }
 
-                    if self.last_arm_msg.elapsed() >= ARM_DELAY {
-                        self.last_arm_msg = Instant::now();
+                        if send_pose {
+                            println!("Setting robot pose to {:?}", self.pose_setter.pos());
+                            let message = set_pose_message(&self.pose_setter.pos());
 
-                        let message = vec![Code(ARM), Code(SET), F32(self.arm_r), F32(self.arm_h)];
+                            self.send_command(&message);
 
-                        if let Some(port) = self.port.as_mut() {
-                            send_message(port, &message);
+                            self.start_new_segment = true;
+                            if self.position_plot_tool == OdoPlotTool::SetPose {
+                                self.position_plot_tool = OdoPlotTool::Pan;
+                            }
                         }
-                    }
+                    });
 
-                    let plot_height = ui.available_height() * 0.8;
+                    ui.horizontal(|ui| {
+                        ui.label("Plot Angle");
+                        ui.add(
+                            egui::DragValue::new(&mut self.position_plot_frame.angle)
+                                .speed(0.5)
+                                .range(0.0..=360.0),
+                        );
 
-                    Plot::new("Arm Plot")
-                        .height(plot_height)
-                        .data_aspect(1.0)
-                        .view_aspect(1.0)
-                        .include_x(-1.0)
-                        .include_x(18.0)
-                        .include_y(-1.0)
-                        .include_y(18.0)
-                        .allow_double_click_reset(false)
-                        .allow_drag(false)
-                        .allow_zoom(false)
-                        .allow_boxed_zoom(false)
-                        .allow_scroll(false)
-                        .allow_axis_zoom_drag(false)
-                        .auto_bounds(eframe::egui::Vec2b { x: false, y: false })
-                        .legend(egui_plot::Legend::default())
-                        .show(ui, |plot_ui| {
-                            if let Some(mouse_pos) = plot_ui.pointer_coordinate() {
-                                let response = plot_ui.response();
-                                if response.hovered()
-                                    && plot_ui.ctx().input(|i| i.pointer.primary_down())
-                                {
-                                    self.arm_r = mouse_pos.x as f32;
-                                    self.arm_h = mouse_pos.y as f32;
-                                }
+                        ui.checkbox(
+                            &mut self.position_plot_frame.align_to_start,
+                            "Align to Start Heading",
+                        );
+                        ui.checkbox(&mut self.position_plot_frame.flip_x, "Flip X");
+                        ui.checkbox(&mut self.position_plot_frame.flip_y, "Flip Y");
+                    });
 
-                                if response.hovered()
-                                    && plot_ui.ctx().input(|i| i.pointer.secondary_clicked())
-                                {
-                                    let message = vec![Code(CLAW), Code(SET)];
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "Origin: ({:.3}, {:.3})",
+                            self.position_plot_frame.origin[0], self.position_plot_frame.origin[1]
+                        ));
+
+                        ui.selectable_value(
+                            &mut self.position_plot_tool,
+                            OdoPlotTool::SetOrigin,
+                            "Click to Set Origin",
+                        );
 
-                                    if let Some(port) = self.port.as_mut() {
-                                        send_message(port, &message);
-                                    }
-                                }
+                        if ui.button("Origin at Start").clicked()
+                            && let Some(start) = self.position_histogram.first()
+                        {
+                            self.position_plot_frame.origin = [start.x as f64, start.y as f64];
+                        }
 
-                                let k = (((mouse_pos.y - 7.0).powf(2.0) + mouse_pos.x.powf(2.0)
-                                    - 128.0)
-                                    / 128.0)
-                                    .acos();
-                                let l = ((mouse_pos.y - 7.0) / mouse_pos.x).atan() + 0.5 * k;
+                        if ui.button("Reset Frame").clicked() {
+                            self.position_plot_frame = PlotFrame::new();
+                        }
+                    });
 
-                                plot_ui.line(
-                                    Line::new(
-                                        "arm ghost",
-                                        vec![
-                                            [0.0, 7.0],
-                                            [8.0 * l.cos(), 8.0 * l.sin() + 7.0],
-                                            [
-                                                8.0 * (l.cos() + (l - k).cos()),
-                                                8.0 * (l.sin() + (l - k).sin()) + 7.0,
-                                            ],
-                                            // [mouse_pos.x, mouse_pos.y]
-                                        ],
-                                    )
-                                    .color(Color32::from_rgba_unmultiplied(0, 255, 255, 80)),
-                                );
-
-                                let k = (((self.arm_h as f64 - 7.0).powf(2.0)
-                                    + (self.arm_r as f64).powf(2.0)
-                                    - 128.0)
-                                    / 128.0)
-                                    .acos();
-                                let l = (((self.arm_h as f64) - 7.0) / (self.arm_r as f64)).atan()
-                                    + 0.5 * k;
-
-                                plot_ui.line(Line::new(
-                                    "current arm",
-                                    vec![
-                                        [0.0, 7.0],
-                                        [8.0 * l.cos(), 8.0 * l.sin() + 7.0],
-                                        [
-                                            8.0 * (l.cos() + (l - k).cos()),
-                                            8.0 * (l.sin() + (l - k).sin()) + 7.0,
-                                        ],
-                                        // [mouse_pos.x, mouse_pos.y]
-                                    ],
-                                ));
+                    ui.collapsing("Waypoints", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(
+                                &mut self.position_plot_tool,
+                                OdoPlotTool::AddWaypoint,
+                                "Click to Add Waypoints",
+                            );
+                            if ui.button("Done").clicked() {
+                                self.position_plot_tool = OdoPlotTool::Pan;
+                            }
+                            if ui.button("Clear Waypoints").clicked() {
+                                self.waypoints.points.clear();
+                                self.waypoints.cross_track_history.clear();
                             }
                         });
 
-                    ui.horizontal(|ui| {
-                        ui.label("TTbl Sensitivity");
-                        ui.add(egui::DragValue::new(&mut self.ttbl_sensitivity).speed(1.0));
-                    });
+                        let mut move_up: Option<usize> = None;
+                        let mut remove: Option<usize> = None;
+                        egui::Grid::new("Waypoint Grid").show(ui, |ui| {
+                            for (i, p) in self.waypoints.points.iter_mut().enumerate() {
+                                ui.label(format!("{}", i));
+                                ui.add(egui::DragValue::new(&mut p[0]).speed(0.005).prefix("x: "));
+                                ui.add(egui::DragValue::new(&mut p[1]).speed(0.005).prefix("y: "));
+                                if ui.button("Up").clicked() && i > 0 {
+                                    move_up = Some(i);
+                                }
+                                if ui.button("Down").clicked() {
+                                    move_up = Some(i + 1);
+                                }
+                                if ui.button("Remove").clicked() {
+                                    remove = Some(i);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                        if let Some(i) = move_up
+                            && i < self.waypoints.points.len()
+                        {
+                            self.waypoints.points.swap(i - 1, i);
+                        }
+                        if let Some(i) = remove {
+                            self.waypoints.points.remove(i);
+                        }
+
+                        if ui
+                            .add_enabled(!self.safety.estopped, egui::Button::new("Send Waypoints"))
+                            .clicked()
+                        {
+                            println!("Sending {} waypoints...", self.waypoints.points.len());
+                            for message in self.waypoints.to_messages() {
+                                self.send_command(&message);
+                            }
+                        }
+
+                        let latest = self.position_histogram.last().and_then(|p| {
+                            self.waypoints.cross_track_error([p.x as f64, p.y as f64])
+                        });
+                        ui.label(match latest {
+                            Some(error) => format!("Cross-track error: {:.3} m", error),
+                            None => String::from("Cross-track error: -"),
+                        });
+
+                        let cross_track: PlotPoints = (0..self.waypoints.cross_track_history.len())
+                            .map(|i| {
+                                [
+                                    i as f64,
+                                    *self.waypoints.cross_track_history.get(i).unwrap(),
+                                ]
+                            })
+                            .collect();
+
+                        Plot::new("Cross Track Plot")
+                            .height(120.0)
+                            .legend(Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui.line(Line::new("Cross-track Error", cross_track));
+                            });
+                    });
+
+                    ui.collapsing("Field Map", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Map File");
+                            ui.text_edit_singleline(&mut self.field_map.path);
+                            if ui.button("Load").clicked() {
+                                self.field_map.load();
+                            }
+                            ui.checkbox(&mut self.field_map.show, "Show Map");
+                        });
+
+                        ui.horizontal(|ui| {
+                            if ui.button("Align by Two Points").clicked() {
+                                self.field_map.picks.clear();
+                                self.position_plot_tool = OdoPlotTool::AlignMap;
+                            }
+                            if ui.button("Reset Alignment").clicked() {
+                                self.field_map.alignment = field_map::MapAlignment::identity();
+                            }
+                            ui.label(format!(
+                                "Rotation {:.1}°, offset ({:.3}, {:.3})",
+                                self.field_map.alignment.angle.to_degrees(),
+                                self.field_map.alignment.offset[0],
+                                self.field_map.alignment.offset[1],
+                            ));
+                        });
+
+                        if self.position_plot_tool == OdoPlotTool::AlignMap {
+                            ui.label(self.field_map.next_pick_label());
+                        }
+                        ui.label(&self.field_map.status);
+                    });
+
+                    ui.collapsing("Trajectory Statistics", |ui| {
+                        let segments =
+                            segment_ranges(self.position_histogram.len(), &self.position_segments);
+
+                        egui::Grid::new("Trajectory Stats Grid")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.label("Segment");
+                                ui.label("Samples");
+                                ui.label("Duration");
+                                ui.label("Distance");
+                                ui.label("Heading Change");
+                                ui.label("Closure Error");
+                                ui.label("Mean Speed");
+                                ui.label("Max Speed");
+                                ui.end_row();
+
+                                let mut rows: Vec<(String, _)> = segments
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(i, range)| {
+                                        (
+                                            format!("{}", i),
+                                            segment_stats(&self.position_histogram[range.clone()]),
+                                        )
+                                    })
+                                    .collect();
+                                if segments.len() > 1 {
+                                    let total = run_stats(&self.position_histogram, &segments);
+                                    rows.push((String::from("Total"), total));
+                                }
+
+                                for (name, stats) in rows {
+                                    ui.label(name);
+                                    ui.label(format!("{}", stats.samples));
+                                    ui.label(format!("{:.1} s", stats.duration));
+                                    ui.label(format!("{:.3} m", stats.distance));
+                                    ui.label(format!("{:.1}°", stats.heading_change.to_degrees()));
+                                    ui.label(format!("{:.3} m", stats.closure_error));
+                                    ui.label(format!("{:.3} m/s", stats.mean_speed));
+                                    ui.label(format!("{:.3} m/s", stats.max_speed));
+                                    ui.end_row();
+                                }
+                            });
+
+                        let mut speed: Vec<[f64; 2]> = Vec::new();
+                        let mut angular_velocity: Vec<[f64; 2]> = Vec::new();
+                        let mut curvature: Vec<[f64; 2]> = Vec::new();
+                        for range in &segments {
+                            for rate in rates(&self.position_histogram[range.clone()]) {
+                                speed.push([rate.t, rate.speed]);
+                                angular_velocity.push([rate.t, rate.angular_velocity]);
+                                if let Some(k) = rate.curvature {
+                                    curvature.push([rate.t, k]);
+                                }
+                            }
+                        }
+
+                        Plot::new("Velocity Plot")
+                            .height(150.0)
+                            .legend(Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui.line(Line::new("Speed (m/s)", speed));
+                                plot_ui
+                                    .line(Line::new("Angular Velocity (rad/s)", angular_velocity));
+                            });
+
+                        Plot::new("Curvature Plot")
+                            .height(150.0)
+                            .legend(Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui
+                                    .points(Points::new("Curvature (1/m)", curvature).radius(1.5));
+                            });
+                    });
+
+                    ui.collapsing("Odometry Calibration", |ui| {
+                        let wizard = &mut self.odo_calibration;
+                        let latest = self.position_histogram.last();
+                        let estopped = self.safety.estopped;
+                        let mut message: Option<Vec<MsgElem>> = None;
+                        let mut motion: Option<Vec<MsgElem>> = None;
+
+                        match wizard.step {
+                            CalibrationStep::Idle | CalibrationStep::Done => {
+                                ui.label(
+                                    "One straight run and one turn assume both wheels are the \
+                                     same size: a left/right diameter mismatch shows up as \
+                                     track width error.",
+                                );
+                                ui.horizontal(|ui| {
+                                    ui.label("Straight Distance");
+                                    ui.add(
+                                        egui::DragValue::new(&mut wizard.target_distance)
+                                            .speed(0.01)
+                                            .range(0.1..=5.0)
+                                            .suffix(" m"),
+                                    );
+                                    ui.label("Drive Speed");
+                                    ui.add(
+                                        egui::DragValue::new(&mut wizard.drive_speed).speed(0.01),
+                                    );
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("Rotation");
+                                    ui.add(
+                                        egui::DragValue::new(&mut wizard.target_angle)
+                                            .speed(1.0)
+                                            .range(90.0..=1800.0)
+                                            .suffix("°"),
+                                    );
+                                    ui.label("Turn Speed");
+                                    ui.add(
+                                        egui::DragValue::new(&mut wizard.turn_speed).speed(0.01),
+                                    );
+                                });
+
+                                if wizard.step == CalibrationStep::Done {
+                                    ui.label(format!(
+                                        "Wheel radius scale: {:.4}, track width scale: {:.4}",
+                                        wizard.wheel_scale, wizard.track_scale
+                                    ));
+                                    if ui
+                                        .add_enabled(
+                                            !estopped,
+                                            egui::Button::new("Send Calibration"),
+                                        )
+                                        .clicked()
+                                    {
+                                        motion = Some(wizard.calibration_message().to_vec());
+                                    }
+                                }
+
+                                match latest {
+                                    Some(pos) => {
+                                        if ui
+                                            .add_enabled(
+                                                !estopped,
+                                                egui::Button::new("Start Straight-Line Test"),
+                                            )
+                                            .clicked()
+                                        {
+                                            motion = Some(wizard.start_straight(pos));
+                                        }
+                                    }
+                                    None => {
+                                        ui.label("Waiting for odometry before starting.");
+                                    }
+                                }
+                            }
+                            CalibrationStep::Straight => {
+                                ui.label(format!(
+                                    "Driving straight: {:.3} / {:.3} m",
+                                    wizard.odo_distance, wizard.target_distance
+                                ));
+                            }
+                            CalibrationStep::MeasureStraight => {
+                                ui.label(format!(
+                                    "Odometry says {:.3} m. Measure the distance actually driven.",
+                                    wizard.odo_distance
+                                ));
+                                ui.horizontal(|ui| {
+                                    ui.label("Measured Distance");
+                                    ui.add(
+                                        egui::DragValue::new(&mut wizard.measured_distance)
+                                            .speed(0.001)
+                                            .suffix(" m"),
+                                    );
+                                });
+                                if let Some(pos) = latest
+                                    && ui
+                                        .add_enabled(
+                                            !estopped && wizard.measured_distance > 0.0,
+                                            egui::Button::new("Start Rotation Test"),
+                                        )
+                                        .clicked()
+                                {
+                                    motion = Some(wizard.start_rotation(pos));
+                                }
+                            }
+                            CalibrationStep::Rotation => {
+                                ui.label(format!(
+                                    "Rotating in place: {:.1} / {:.1}°",
+                                    wizard.odo_angle, wizard.target_angle
+                                ));
+                            }
+                            CalibrationStep::MeasureRotation => {
+                                ui.label(format!(
+                                    "Odometry says {:.1}°. Measure the angle actually turned.",
+                                    wizard.odo_angle
+                                ));
+                                ui.horizontal(|ui| {
+                                    ui.label("Measured Angle");
+                                    ui.add(
+                                        egui::DragValue::new(&mut wizard.measured_angle)
+                                            .speed(0.5)
+                                            .suffix("°"),
+                                    );
+                                });
+                                if ui
+                                    .add_enabled(
+                                        wizard.measured_angle != 0.0,
+                                        egui::Button::new("Compute"),
+                                    )
+                                    .clicked()
+                                {
+                                    wizard.compute();
+                                }
+                            }
+                        }
+
+                        if !wizard.status.is_empty() {
+                            ui.colored_label(Color32::YELLOW, &wizard.status);
+                        }
+
+                        if wizard.step != CalibrationStep::Idle
+                            && wizard.step != CalibrationStep::Done
+                            && ui.button("Abort").clicked()
+                        {
+                            message = Some(wizard.abort());
+                        }
+
+                        // The abort is a stop, so it goes out even while latched.
+                        if let Some(message) = message
+                            && let Some(port) = self.port.as_mut()
+                        {
+                            send_message(port, &message);
+                        }
+                        if let Some(message) = motion {
+                            self.send_command(&message);
+                        }
+                    });
+
+                    ui.collapsing("Robot Configuration", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.show_footprint, "Show Footprint");
+                            ui.label("Ghost Every N Samples");
+                            ui.add(
+                                egui::DragValue::new(&mut self.footprint_ghost_interval)
+                                    .range(0..=1000),
+                            );
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Front Offset");
+                            if ui
+                                .add(
+                                    egui::DragValue::new(&mut self.robot_config.front_offset)
+                                        .speed(0.001)
+                                        .suffix(" m"),
+                                )
+                                .changed()
+                            {
+                                // The front trace was recorded with the old offset.
+                                self.position_histogram_front = self
+                                    .position_histogram
+                                    .iter()
+                                    .map(|p| self.robot_config.front_point(p))
+                                    .collect();
+                            }
+                            ui.label("Rear Offset");
+                            ui.add(
+                                egui::DragValue::new(&mut self.robot_config.rear_offset)
+                                    .speed(0.001)
+                                    .suffix(" m"),
+                            );
+                            ui.label("Wheelbase");
+                            ui.add(
+                                egui::DragValue::new(&mut self.robot_config.wheelbase)
+                                    .speed(0.001)
+                                    .range(0.0..=f32::INFINITY)
+                                    .suffix(" m"),
+                            );
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Arm Position");
+                            ui.add(
+                                egui::DragValue::new(&mut self.robot_config.arm_position[0])
+                                    .speed(0.001)
+                                    .prefix("x: "),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut self.robot_config.arm_position[1])
+                                    .speed(0.001)
+                                    .prefix("y: "),
+                            );
+                        });
+                    });
+                }
+                View::DriveTeleop => {
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.drive_teleop.mode, DriveMode::Arcade, "Arcade");
+                        ui.radio_value(&mut self.drive_teleop.mode, DriveMode::Tank, "Tank");
+                        ui.label("Max speed");
+                        ui.add(
+                            egui::DragValue::new(&mut self.drive_teleop.max_speed)
+                                .speed(0.01)
+                                .suffix(" m/s"),
+                        );
+                        ui.label("Max turn rate");
+                        ui.add(
+                            egui::DragValue::new(&mut self.drive_teleop.max_turn)
+                                .speed(0.05)
+                                .suffix(" rad/s"),
+                        );
+                    });
+
+                    // Only one source drives the base at a time. While keyboard or
+                    // gamepad teleop is on the sticks are disabled and read as
+                    // centred, so this view sends a single stop and goes quiet.
+                    if self.teleop.enabled {
+                        ui.colored_label(
+                            Color32::YELLOW,
+                            "Teleoperation has the drive base, disable it to use these sticks.",
+                        );
+                    }
+                    ui.add_enabled_ui(!self.teleop.enabled, |ui| {
+                        ui.horizontal(|ui| match self.drive_teleop.mode {
+                            DriveMode::Arcade => {
+                                drive_teleop::joystick(
+                                    ui,
+                                    &mut self.drive_teleop.left_stick,
+                                    200.0,
+                                    false,
+                                );
+                            }
+                            DriveMode::Tank => {
+                                drive_teleop::joystick(
+                                    ui,
+                                    &mut self.drive_teleop.left_stick,
+                                    200.0,
+                                    true,
+                                );
+                                drive_teleop::joystick(
+                                    ui,
+                                    &mut self.drive_teleop.right_stick,
+                                    200.0,
+                                    true,
+                                );
+                            }
+                        });
+                    });
+
+                    if let Some(message) = self
+                        .drive_teleop
+                        .update(self.robot_config.wheelbase)
+                        .filter(|_| !self.safety.estopped)
+                    {
+                        self.send_command(&message);
+                    }
+
+                    let [left, right] = self.drive_teleop.commanded;
+                    ui.label(format!(
+                        "Commanded: left {:.3} m/s, right {:.3} m/s",
+                        left, right
+                    ));
+                    match self.drive_teleop.measured {
+                        Some([left, right]) => ui.label(format!(
+                            "Measured: left {:.3} m/s, right {:.3} m/s",
+                            left, right
+                        )),
+                        None => ui.label("Measured: no VELOCITY telemetry received."),
+                    };
+
+                    let series = |index: usize| -> PlotPoints {
+                        (0..self.drive_teleop.history.len())
+                            .map(|i| [i as f64, self.drive_teleop.history.get(i).unwrap()[index]])
+                            .collect()
+                    };
+
+                    Plot::new("Wheel Speed Plot")
+                        .legend(Legend::default())
+                        .show(ui, |plot_ui| {
+                            plot_ui.line(
+                                Line::new("Commanded Left", series(0))
+                                    .color(Color32::LIGHT_BLUE)
+                                    .style(LineStyle::dashed_loose()),
+                            );
+                            plot_ui.line(
+                                Line::new("Commanded Right", series(1))
+                                    .color(Color32::LIGHT_RED)
+                                    .style(LineStyle::dashed_loose()),
+                            );
+                            plot_ui.line(
+                                Line::new("Measured Left", series(2)).color(Color32::LIGHT_BLUE),
+                            );
+                            plot_ui.line(
+                                Line::new("Measured Right", series(3)).color(Color32::LIGHT_RED),
+                            );
+                        });
+                }
+                View::ArmControl => {
+                    for event in ctx.input(|i| i.events.clone()) {
+                        if let egui::Event::MouseWheel {
+                            unit: _,
+                            delta,
+                            modifiers: _,
+                        } = event
+                        {
+                            self.ttbl_val += delta.y * self.ttbl_sensitivity;
+                        }
+                    }
+
+                    if self.safety.estopped {
+                        self.ttbl_val = 0.0;
+                    }
+
+                    if let Some(step) =
+                        self.arm_sequence
+                            .step(&self.arm_geometry, self.arm_elbow, &self.arm_limits)
+                    {
+                        self.arm_r = step.arm_r;
+                        self.arm_h = step.arm_h;
+
+                        self.ttbl_val = step.turntable - self.ttbl_angle;
+
+                        if let Some(closed) = step.claw_closed {
+                            let message = if closed {
+                                self.claw.close_message()
+                            } else {
+                                self.claw.open_message()
+                            };
+
+                            self.send_command(&message);
+                        }
+                    }
+
+                    const TTBL_DELAY: Duration = Duration::from_millis(50);
+
+                    const ARM_DELAY: Duration = Duration::from_millis(80);
+
+                    if self.last_ttb_msg.elapsed() >= TTBL_DELAY && self.ttbl_val != 0.0 {
+                        self.last_ttb_msg = Instant::now();
+                        self.turntable_step(self.ttbl_val);
+                        self.ttbl_val = 0.0;
+                    }
+
+                    if self.last_arm_msg.elapsed() >= ARM_DELAY && !self.safety.estopped {
+                        self.last_arm_msg = Instant::now();
+
+                        let message = vec![Code(ARM), Code(SET), F32(self.arm_r), F32(self.arm_h)];
+
+                        self.send_command(&message);
+                    }
+
+                    let plot_height = ui.available_height() * 0.8;
+
+                    let workspace = self
+                        .arm_workspace
+                        .get_or_insert_with(|| {
+                            arm_workspace(&self.arm_limits, &self.arm_geometry, self.arm_elbow)
+                        })
+                        .clone();
+
+                    ui.horizontal(|ui| {
+                        Plot::new("Arm Plot")
+                            .height(plot_height)
+                            .data_aspect(1.0)
+                            .view_aspect(1.0)
+                            .include_x(-1.0)
+                            .include_x(18.0)
+                            .include_y(-1.0)
+                            .include_y(18.0)
+                            .allow_double_click_reset(false)
+                            .allow_drag(false)
+                            .allow_zoom(false)
+                            .allow_boxed_zoom(false)
+                            .allow_scroll(false)
+                            .allow_axis_zoom_drag(false)
+                            .auto_bounds(eframe::egui::Vec2b { x: false, y: false })
+                            .legend(egui_plot::Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui.points(
+                                    Points::new("Workspace", workspace)
+                                        .shape(MarkerShape::Square)
+                                        .radius(2.5)
+                                        .color(Color32::from_rgba_unmultiplied(0, 200, 0, 25)),
+                                );
+                                for zone in &self.arm_limits.keep_out {
+                                    plot_ui.polygon(
+                                        Polygon::new("Keep Out", zone.outline()).fill_color(
+                                            Color32::from_rgba_unmultiplied(255, 0, 0, 30),
+                                        ),
+                                    );
+                                }
+
+                                if let Some(mouse_pos) = plot_ui.pointer_coordinate() {
+                                    let response = plot_ui.response();
+                                    if response.hovered()
+                                        && plot_ui.ctx().input(|i| i.pointer.primary_down())
+                                    {
+                                        self.set_arm_target(mouse_pos.x, mouse_pos.y);
+                                    }
+
+                                    if response.hovered()
+                                        && !self.safety.estopped
+                                        && plot_ui.ctx().input(|i| i.pointer.secondary_clicked())
+                                    {
+                                        let message = self.claw.toggle_message();
+
+                                        self.send_command(&message);
+                                    }
+
+                                    if let Some(ghost) = self.arm_geometry.linkage(
+                                        mouse_pos.x,
+                                        mouse_pos.y,
+                                        self.arm_elbow,
+                                    ) {
+                                        plot_ui.line(Line::new("arm ghost", ghost).color(
+                                            Color32::from_rgba_unmultiplied(0, 255, 255, 80),
+                                        ));
+                                    }
+                                }
+
+                                if let (Some(shoulder), Some(elbow)) =
+                                    (self.shoulder_measured, self.elbow_measured)
+                                {
+                                    plot_ui.line(
+                                        Line::new(
+                                            "measured arm",
+                                            self.arm_geometry
+                                                .forward(JointAngles { shoulder, elbow })
+                                                .to_vec(),
+                                        )
+                                        .color(Color32::ORANGE)
+                                        .width(2.0),
+                                    );
+                                }
+                                if let Some(current) = self.arm_geometry.linkage(
+                                    self.arm_r as f64,
+                                    self.arm_h as f64,
+                                    self.arm_elbow,
+                                ) {
+                                    for jaw in self.claw.jaws(current[1], current[2], 1.5) {
+                                        plot_ui.line(Line::new("claw", jaw).width(2.0));
+                                    }
+                                    plot_ui.line(Line::new("current arm", current));
+                                }
+                            });
+
+                        let circle = |radius: f64| -> Vec<[f64; 2]> {
+                            (0..=64)
+                                .map(|i| {
+                                    let a = i as f64 / 64.0 * std::f64::consts::TAU;
+                                    [radius * a.cos(), radius * a.sin()]
+                                })
+                                .collect()
+                        };
+                        let pointer = |angle: f32, radius: f64| -> Vec<[f64; 2]> {
+                            let a = (angle as f64).to_radians();
+                            vec![[0.0, 0.0], [radius * a.cos(), radius * a.sin()]]
+                        };
+                        let reach = self.arm_geometry.max_reach();
+
+                        Plot::new("Turntable Plot")
+                            .height(plot_height)
+                            .width(plot_height)
+                            .data_aspect(1.0)
+                            .include_x(-reach)
+                            .include_x(reach)
+                            .include_y(-reach)
+                            .include_y(reach)
+                            .allow_double_click_reset(false)
+                            .allow_drag(false)
+                            .allow_zoom(false)
+                            .allow_boxed_zoom(false)
+                            .allow_scroll(false)
+                            .allow_axis_zoom_drag(false)
+                            .x_axis_label("forward")
+                            .y_axis_label("left")
+                            .legend(egui_plot::Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui.line(
+                                    Line::new("Reach", circle(reach))
+                                        .color(Color32::from_rgba_unmultiplied(0, 200, 0, 80)),
+                                );
+                                plot_ui.line(
+                                    Line::new("Claw Radius", circle(self.arm_r as f64))
+                                        .color(Color32::GRAY)
+                                        .style(LineStyle::dashed_loose()),
+                                );
+                                plot_ui.line(
+                                    Line::new(
+                                        "Commanded",
+                                        pointer(self.ttbl_angle, self.arm_r as f64),
+                                    )
+                                    .width(3.0),
+                                );
+                                if let Some(measured) = self.ttbl_measured_angle {
+                                    plot_ui.line(
+                                        Line::new("Measured", pointer(measured, self.arm_r as f64))
+                                            .color(Color32::ORANGE)
+                                            .width(2.0),
+                                    );
+                                }
+
+                                if let Some(mouse_pos) = plot_ui.pointer_coordinate() {
+                                    let angle = mouse_pos.y.atan2(mouse_pos.x).to_degrees() as f32;
+                                    plot_ui.line(
+                                        Line::new("Target", pointer(angle, reach)).color(
+                                            Color32::from_rgba_unmultiplied(0, 255, 255, 80),
+                                        ),
+                                    );
+
+                                    if plot_ui.response().clicked() && !self.safety.estopped {
+                                        let message =
+                                            vec![Code(TTBL), Code(SET), Code(ANGLE), F32(angle)];
+
+                                        self.send_command(&message);
+
+                                        // Drop any relative scroll still pending.
+                                        self.ttbl_val = 0.0;
+                                        self.ttbl_angle = angle;
+                                    }
+                                }
+                            });
+                    });
+
+                    if let Some(warning) = &self.arm_warning {
+                        ui.colored_label(Color32::YELLOW, warning);
+                    }
+
+                    ui.label(format!(
+                        "Turntable: {:.1}° commanded, {} measured",
+                        self.ttbl_angle,
+                        match self.ttbl_measured_angle {
+                            Some(angle) => format!("{:.1}°", angle),
+                            None => String::from("not"),
+                        },
+                    ));
+
+                    ui.horizontal(|ui| {
+                        let mut message = None;
+                        if ui
+                            .add_enabled(!self.safety.estopped, egui::Button::new("Open Claw"))
+                            .clicked()
+                        {
+                            message = Some(self.claw.open_message());
+                        }
+                        if ui
+                            .add_enabled(!self.safety.estopped, egui::Button::new("Close Claw"))
+                            .clicked()
+                        {
+                            message = Some(self.claw.close_message());
+                        }
+
+                        let mut angle = self.claw.commanded;
+                        let (min, max) = if self.claw.open_angle < self.claw.closed_angle {
+                            (self.claw.open_angle, self.claw.closed_angle)
+                        } else {
+                            (self.claw.closed_angle, self.claw.open_angle)
+                        };
+                        if ui
+                            .add_enabled(
+                                !self.safety.estopped,
+                                egui::Slider::new(&mut angle, min..=max).text("Claw Angle"),
+                            )
+                            .changed()
+                        {
+                            message = Some(self.claw.set_message(angle));
+                        }
+
+                        if let Some(message) = message {
+                            self.send_command(&message);
+                        }
+
+                        let (color, text) = match self.claw.grip() {
+                            GripState::NoFeedback => (Color32::GRAY, "no feedback"),
+                            GripState::Open => (Color32::GRAY, "open"),
+                            GripState::Empty => (Color32::YELLOW, "closed, nothing held"),
+                            GripState::Gripping => (Color32::GREEN, "gripping"),
+                        };
+                        ui.colored_label(color, format!("Claw {}", text));
+                        if let (Some(angle), Some(current)) =
+                            (self.claw.measured_angle, self.claw.measured_current)
+                        {
+                            ui.label(format!("({:.1}°, {:.2} A)", angle, current));
+                        }
+                    });
+
+                    ui.collapsing("Claw Settings", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Open Angle");
+                            ui.add(egui::DragValue::new(&mut self.claw.open_angle).speed(0.5));
+                            ui.label("Closed Angle");
+                            ui.add(egui::DragValue::new(&mut self.claw.closed_angle).speed(0.5));
+                            ui.label("Grip Current");
+                            ui.add(
+                                egui::DragValue::new(&mut self.claw.grip_current)
+                                    .speed(0.01)
+                                    .suffix(" A"),
+                            );
+                            ui.label("Grip Margin");
+                            ui.add(
+                                egui::DragValue::new(&mut self.claw.grip_margin)
+                                    .speed(0.1)
+                                    .suffix("°"),
+                            );
+                        });
+
+                        let current: PlotPoints = (0..self.claw.current_history.len())
+                            .map(|i| [i as f64, *self.claw.current_history.get(i).unwrap()])
+                            .collect();
+                        Plot::new("Claw Current Plot")
+                            .height(120.0)
+                            .legend(Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui.line(Line::new("Claw Current", current));
+                                plot_ui.hline(
+                                    HLine::new("Grip Current", self.claw.grip_current as f64)
+                                        .color(Color32::GREEN),
+                                );
+                            });
+                    });
+
+                    ui.checkbox(&mut self.show_arm_3d, "Show 3D View");
+
+                    let commanded = self.arm_geometry.linkage(
+                        self.arm_r as f64,
+                        self.arm_h as f64,
+                        self.arm_elbow,
+                    );
+                    let scene = ArmScene {
+                        turntable: self.ttbl_measured_angle.unwrap_or(self.ttbl_angle),
+                        turntable_radius: 4.0,
+                        jaws: match &commanded {
+                            Some(linkage) => self.claw.jaws(linkage[1], linkage[2], 1.5).to_vec(),
+                            None => Vec::new(),
+                        },
+                        commanded,
+                        measured: match (self.shoulder_measured, self.elbow_measured) {
+                            (Some(shoulder), Some(elbow)) => Some(
+                                self.arm_geometry
+                                    .forward(JointAngles { shoulder, elbow })
+                                    .to_vec(),
+                            ),
+                            _ => None,
+                        },
+                        reach: self.arm_geometry.max_reach() as f32,
+                    };
+                    egui::Window::new("3D Arm View")
+                        .open(&mut self.show_arm_3d)
+                        .default_size(egui::vec2(400.0, 400.0))
+                        .show(ctx, |ui| {
+                            ui.label("Drag to orbit, right-drag to zoom.");
+                            arm_view_3d::show(ui, &mut self.arm_camera, &scene);
+                        });
+
+                    ui.collapsing("Joint Tracking", |ui| {
+                        ui.label(format!(
+                            "Measured shoulder {}, elbow {}",
+                            match self.shoulder_measured {
+                                Some(angle) => format!("{:.1}°", angle.to_degrees()),
+                                None => String::from("-"),
+                            },
+                            match self.elbow_measured {
+                                Some(angle) => format!("{:.1}°", angle.to_degrees()),
+                                None => String::from("-"),
+                            },
+                        ));
+
+                        let shoulder_error: PlotPoints = (0..self.arm_tracking_error.len())
+                            .map(|i| {
+                                [
+                                    i as f64,
+                                    self.arm_tracking_error.get(i).unwrap()[0].to_degrees(),
+                                ]
+                            })
+                            .collect();
+                        let elbow_error: PlotPoints = (0..self.arm_tracking_error.len())
+                            .map(|i| {
+                                [
+                                    i as f64,
+                                    self.arm_tracking_error.get(i).unwrap()[1].to_degrees(),
+                                ]
+                            })
+                            .collect();
+
+                        Plot::new("Joint Tracking Plot")
+                            .height(150.0)
+                            .legend(Legend::default())
+                            .y_axis_label("error (°)")
+                            .show(ui, |plot_ui| {
+                                plot_ui.line(Line::new("Shoulder Error", shoulder_error));
+                                plot_ui.line(Line::new("Elbow Error", elbow_error));
+                            });
+
+                        if ui.button("Clear Tracking Error").clicked() {
+                            self.arm_tracking_error.clear();
+                        }
+                    });
+
+                    ui.collapsing("Teach Mode", |ui| {
+                        let sequence = &mut self.arm_sequence;
+                        let current = Keyframe {
+                            name: String::new(),
+                            arm_r: self.arm_r,
+                            arm_h: self.arm_h,
+                            turntable: self.ttbl_angle,
+                            claw_closed: self.claw.is_closed(),
+                            duration: 0.0,
+                        };
+
+                        ui.horizontal(|ui| {
+                            if ui.button("Record Keyframe").clicked() {
+                                sequence.record(
+                                    current.clone(),
+                                    &self.arm_geometry,
+                                    self.arm_elbow,
+                                    &self.arm_limits,
+                                );
+                            }
+                            if ui.button("Clear").clicked() {
+                                sequence.keyframes.clear();
+                                sequence.abort();
+                            }
+                            ui.radio_value(
+                                &mut sequence.interpolation,
+                                Interpolation::Joint,
+                                "Joint Space",
+                            );
+                            ui.radio_value(
+                                &mut sequence.interpolation,
+                                Interpolation::Cartesian,
+                                "Cartesian",
+                            );
+                        });
+
+                        let violations = sequence.violations(
+                            &self.arm_geometry,
+                            self.arm_elbow,
+                            &self.arm_limits,
+                        );
+                        let mut move_up: Option<usize> = None;
+                        let mut remove: Option<usize> = None;
+                        egui::Grid::new("Keyframe Grid").show(ui, |ui| {
+                            ui.label("");
+                            ui.label("Name");
+                            ui.label("r");
+                            ui.label("h");
+                            ui.label("Turntable");
+                            ui.label("Claw Closed");
+                            ui.label("Duration");
+                            ui.end_row();
+
+                            let target = sequence.playback.as_ref().map(|p| p.target);
+                            for (i, keyframe) in sequence.keyframes.iter_mut().enumerate() {
+                                match violations.iter().find(|(j, _)| *j == i) {
+                                    Some((_, violation)) => ui
+                                        .colored_label(Color32::RED, "⚠")
+                                        .on_hover_text(format!("{:?}", violation)),
+                                    None => ui.colored_label(
+                                        Color32::YELLOW,
+                                        if target == Some(i) { "▶" } else { "" },
+                                    ),
+                                };
+                                ui.text_edit_singleline(&mut keyframe.name);
+                                ui.add(egui::DragValue::new(&mut keyframe.arm_r).speed(0.05));
+                                ui.add(egui::DragValue::new(&mut keyframe.arm_h).speed(0.05));
+                                ui.add(egui::DragValue::new(&mut keyframe.turntable).speed(0.5));
+                                ui.checkbox(&mut keyframe.claw_closed, "");
+                                ui.add(
+                                    egui::DragValue::new(&mut keyframe.duration)
+                                        .speed(0.05)
+                                        .range(0.0..=60.0)
+                                        .suffix(" s"),
+                                );
+                                if ui.button("Up").clicked() && i > 0 {
+                                    move_up = Some(i);
+                                }
+                                if ui.button("Down").clicked() {
+                                    move_up = Some(i + 1);
+                                }
+                                if ui.button("Remove").clicked() {
+                                    remove = Some(i);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                        if let Some(i) = move_up
+                            && i < sequence.keyframes.len()
+                        {
+                            sequence.keyframes.swap(i - 1, i);
+                        }
+                        if let Some(i) = remove {
+                            sequence.keyframes.remove(i);
+                            sequence.abort();
+                        }
+
+                        ui.horizontal(|ui| match &sequence.playback {
+                            None => {
+                                if ui
+                                    .add_enabled(!self.safety.estopped, egui::Button::new("Play"))
+                                    .clicked()
+                                {
+                                    sequence.play(
+                                        current,
+                                        &self.arm_geometry,
+                                        self.arm_elbow,
+                                        &self.arm_limits,
+                                    );
+                                }
+                            }
+                            Some(playback) => {
+                                let label = if playback.paused { "Resume" } else { "Pause" };
+                                if ui.button(label).clicked() {
+                                    sequence.toggle_pause();
+                                }
+                                if ui.button("Abort").clicked() {
+                                    sequence.abort();
+                                }
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Sequence File");
+                            ui.text_edit_singleline(&mut sequence.path);
+                            if ui.button("Save").clicked() {
+                                sequence.save();
+                            }
+                            if ui.button("Load").clicked() {
+                                sequence.load();
+                            }
+                        });
+                        ui.label(&sequence.status);
+                    });
+
+                    let geometry = self.arm_geometry;
+                    let elbow = self.arm_elbow;
+                    let limits = self.arm_limits.clone();
+
+                    ui.collapsing("Arm Geometry", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Upper Arm");
+                            ui.add(
+                                egui::DragValue::new(&mut self.arm_geometry.upper_arm)
+                                    .speed(0.05)
+                                    .range(0.1..=100.0),
+                            );
+                            ui.label("Forearm");
+                            ui.add(
+                                egui::DragValue::new(&mut self.arm_geometry.forearm)
+                                    .speed(0.05)
+                                    .range(0.1..=100.0),
+                            );
+                            ui.label("Base Height");
+                            ui.add(
+                                egui::DragValue::new(&mut self.arm_geometry.base_height)
+                                    .speed(0.05),
+                            );
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Shoulder Offset");
+                            ui.add(
+                                egui::DragValue::new(&mut self.arm_geometry.shoulder_offset)
+                                    .speed(0.01)
+                                    .suffix(" rad"),
+                            );
+                            ui.label("Elbow Offset");
+                            ui.add(
+                                egui::DragValue::new(&mut self.arm_geometry.elbow_offset)
+                                    .speed(0.01)
+                                    .suffix(" rad"),
+                            );
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut self.arm_elbow, ElbowConfig::Up, "Elbow Up");
+                            ui.radio_value(&mut self.arm_elbow, ElbowConfig::Down, "Elbow Down");
+                        });
+                    });
+
+                    ui.collapsing("Joint Limits and Keep-Out Zones", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Shoulder");
+                            ui.add(
+                                egui::DragValue::new(&mut self.arm_limits.shoulder[0])
+                                    .speed(0.01)
+                                    .prefix("min: ")
+                                    .suffix(" rad"),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut self.arm_limits.shoulder[1])
+                                    .speed(0.01)
+                                    .prefix("max: ")
+                                    .suffix(" rad"),
+                            );
+                            ui.label("Elbow");
+                            ui.add(
+                                egui::DragValue::new(&mut self.arm_limits.elbow[0])
+                                    .speed(0.01)
+                                    .prefix("min: ")
+                                    .suffix(" rad"),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut self.arm_limits.elbow[1])
+                                    .speed(0.01)
+                                    .prefix("max: ")
+                                    .suffix(" rad"),
+                            );
+                        });
+
+                        let mut remove: Option<usize> = None;
+                        egui::Grid::new("Keep Out Grid").show(ui, |ui| {
+                            for (i, zone) in self.arm_limits.keep_out.iter_mut().enumerate() {
+                                ui.text_edit_singleline(&mut zone.name);
+                                ui.add(
+                                    egui::DragValue::new(&mut zone.min[0])
+                                        .speed(0.1)
+                                        .prefix("r min: "),
+                                );
+                                ui.add(
+                                    egui::DragValue::new(&mut zone.max[0])
+                                        .speed(0.1)
+                                        .prefix("r max: "),
+                                );
+                                ui.add(
+                                    egui::DragValue::new(&mut zone.min[1])
+                                        .speed(0.1)
+                                        .prefix("h min: "),
+                                );
+                                ui.add(
+                                    egui::DragValue::new(&mut zone.max[1])
+                                        .speed(0.1)
+                                        .prefix("h max: "),
+                                );
+                                if ui.button("Remove").clicked() {
+                                    remove = Some(i);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                        if let Some(i) = remove {
+                            self.arm_limits.keep_out.remove(i);
+                        }
+
+                        if ui.button("Add Keep-Out Zone").clicked() {
+                            self.arm_limits.keep_out.push(KeepOut {
+                                name: String::from("Zone"),
+                                min: [0.0, 0.0],
+                                max: [1.0, 1.0],
+                            });
+                        }
+                    });
+
+                    if self.arm_geometry != geometry
+                        || self.arm_elbow != elbow
+                        || self.arm_limits != limits
+                    {
+                        self.arm_workspace = None;
+                        self.set_arm_target(self.arm_r as f64, self.arm_h as f64);
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("TTbl Sensitivity");
+                        ui.add(
+                            egui::DragValue::new(&mut self.ttbl_sensitivity)
+                                .speed(1.0)
+                                .suffix("°/step"),
+                        );
+                    });
 
                     // println!("ASDSADA");
                 }
@@ -666,41 +2558,862 @@ impl eframe::App for SerialInterfaceApp {
                         })
                         .collect();
 
-                    let lidar_distance_log: PlotPoints = (0..self.lidar_distance_log.len())
-                        .map(|i| [i as f64, (*self.lidar_distance_log.get(i).unwrap()) as f64])
+                    let convolution: Vec<f32> =
+                        self.lidar_convolution_histogram.iter().copied().collect();
+                    let peaks = self.lidar_peaks.detect(&convolution);
+                    let peak_lines: Vec<Line> = peaks
+                        .iter()
+                        .map(|peak| {
+                            let points: PlotPoints = (peak.start..peak.end)
+                                .map(|i| [i as f64, convolution[i] as f64])
+                                .collect();
+                            Line::new("Detected", points).color(Color32::RED).width(3.0)
+                        })
+                        .collect();
+                    let peak_maxima: PlotPoints = peaks
+                        .iter()
+                        .map(|peak| [peak.max_index as f64, peak.max_value as f64])
                         .collect();
 
-                    let lidar_convolution_log: PlotPoints = (0..self.lidar_convolution_log.len())
-                        .map(|i| {
-                            [
-                                i as f64,
-                                (*self.lidar_convolution_log.get(i).unwrap()) as f64,
-                            ]
-                        })
+                    let lidar_distance_log: PlotPoints = (0..self.lidar_log.distance.len())
+                        .map(|i| [i as f64, self.lidar_log.distance[i] as f64])
+                        .collect();
+
+                    let lidar_convolution_log: PlotPoints = (0..self.lidar_log.convolution.len())
+                        .map(|i| [i as f64, self.lidar_log.convolution[i] as f64])
                         .collect();
 
                     let height = ui.available_height() * 0.4;
 
-                    Plot::new("histogram plot")
+                    let origin = self.robot_config.arm_position;
+                    let sweep_count = self.lidar_scan.sweeps.len();
+                    let sweeps: Vec<Points> = self
+                        .lidar_scan
+                        .sweeps
+                        .iter()
+                        .enumerate()
+                        .map(|(i, sweep)| {
+                            // Older sweeps fade out.
+                            let alpha = 255 * (i + 1) / (sweep_count + 1);
+                            let points: PlotPoints = sweep
+                                .iter()
+                                .map(|p| LidarScan::to_robot(origin, p))
+                                .collect();
+                            Points::new("Previous Sweeps", points)
+                                .radius(2.0)
+                                .color(Color32::from_rgba_unmultiplied(100, 200, 255, alpha as u8))
+                        })
+                        .collect();
+                    let current_sweep: PlotPoints = self
+                        .lidar_scan
+                        .current
+                        .iter()
+                        .map(|p| LidarScan::to_robot(origin, p))
+                        .collect();
+                    let footprint = self.robot_config.footprint(&Pos {
+                        x: 0.0,
+                        y: 0.0,
+                        theta: 0.0,
+                        t: 0.0,
+                    });
+                    let beam = self.lidar_scan.current.last().map(|p| {
+                        vec![
+                            [origin[0] as f64, origin[1] as f64],
+                            LidarScan::to_robot(origin, p),
+                        ]
+                    });
+
+                    ui.columns(2, |columns| {
+                        let ui = &mut columns[0];
+
+                        Plot::new("histogram plot")
+                            .height(height)
+                            .legend(Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui.line(Line::new("Distance", lidar_distance));
+                                plot_ui.line(Line::new("Convolution", lidar_convolution));
+                                plot_ui.hline(
+                                    HLine::new("Threshold", self.lidar_peaks.threshold)
+                                        .color(Color32::RED),
+                                );
+                                plot_ui.hline(
+                                    HLine::new(
+                                        "Release",
+                                        self.lidar_peaks.threshold - self.lidar_peaks.hysteresis,
+                                    )
+                                    .color(Color32::LIGHT_RED)
+                                    .style(LineStyle::dashed_loose()),
+                                );
+                                for line in peak_lines {
+                                    plot_ui.line(line);
+                                }
+                                plot_ui.points(
+                                    Points::new("Peaks", peak_maxima)
+                                        .shape(MarkerShape::Diamond)
+                                        .radius(5.0)
+                                        .color(Color32::RED),
+                                );
+                            });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Threshold");
+                            ui.add(egui::DragValue::new(&mut self.lidar_peaks.threshold));
+                            ui.label("Hysteresis");
+                            ui.add(
+                                egui::DragValue::new(&mut self.lidar_peaks.hysteresis)
+                                    .range(0.0..=f32::MAX),
+                            );
+                            ui.label("Min width");
+                            ui.add(
+                                egui::DragValue::new(&mut self.lidar_peaks.min_width)
+                                    .range(1..=1000),
+                            );
+                            if ui.button("Send Detection Parameters").clicked() {
+                                let message = self.lidar_peaks.parameters_message();
+
+                                self.send_command(&message);
+                            }
+                        });
+                        ui.label(format!("{} peaks detected.", peaks.len()));
+
+                        Plot::new("log plot")
+                            .height(height)
+                            .legend(Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui.line(Line::new("Distance", lidar_distance_log));
+                                plot_ui.line(Line::new("Convolution", lidar_convolution_log));
+                            });
+
+                        ui.horizontal(|ui| {
+                            let label = if self.lidar_log.capturing {
+                                "Stop Log Capture"
+                            } else {
+                                "Start Log Capture"
+                            };
+                            if ui.button(label).clicked() {
+                                let start = !self.lidar_log.capturing;
+                                let message = self.lidar_log.capture_message(start);
+
+                                if self.send_command(&message) {
+                                    self.lidar_log.capturing = start;
+                                } else {
+                                    self.lidar_log.status =
+                                        String::from("Couldn't send the capture command.");
+                                }
+                            }
+
+                            if ui.button("Clear Log").clicked() {
+                                self.lidar_log.clear();
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(&mut self.lidar_log.path);
+                            if ui.button("Save Log").clicked() {
+                                self.lidar_log.save();
+                            }
+                        });
+                        ui.label(format!(
+                            "{} samples. {}",
+                            self.lidar_log.distance.len(),
+                            self.lidar_log.status
+                        ));
+
+                        let ui = &mut columns[1];
+
+                        Plot::new("Scan Plot")
+                            .height(2.0 * height)
+                            .data_aspect(1.0)
+                            .legend(Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui
+                                    .line(Line::new("Robot", footprint).color(Color32::LIGHT_GRAY));
+                                for sweep in sweeps {
+                                    plot_ui.points(sweep);
+                                }
+                                plot_ui.points(
+                                    Points::new("Current Sweep", current_sweep)
+                                        .radius(2.5)
+                                        .color(Color32::YELLOW),
+                                );
+                                if let Some(beam) = beam {
+                                    plot_ui.line(
+                                        Line::new("Beam", beam).color(
+                                            Color32::from_rgba_unmultiplied(255, 255, 0, 80),
+                                        ),
+                                    );
+                                }
+                            });
+
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.lidar_scan.enabled, "Record scan");
+                            ui.label("Sweeps kept");
+                            ui.add(
+                                egui::DragValue::new(&mut self.lidar_scan.persistence)
+                                    .range(1..=50),
+                            );
+                            ui.label("Metres per unit");
+                            ui.add(
+                                egui::DragValue::new(&mut self.lidar_scan.distance_scale)
+                                    .speed(0.0001),
+                            );
+                            ui.label("Max range");
+                            ui.add(
+                                egui::DragValue::new(&mut self.lidar_scan.max_range)
+                                    .speed(0.05)
+                                    .suffix(" m"),
+                            );
+                            if ui.button("Clear Scan").clicked() {
+                                self.lidar_scan.clear();
+                            }
+                        });
+                        ui.label(format!(
+                            "Turntable angle from {}.",
+                            if self.ttbl_measured_angle.is_some() {
+                                "telemetry"
+                            } else {
+                                "commands"
+                            }
+                        ));
+
+                        ui.collapsing("Distance Calibration", |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Streaming");
+                                for stream in [RAW, CONVERTED] {
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.lidar_calibration.stream == stream,
+                                            format!("{:?}", stream),
+                                        ))
+                                        .clicked()
+                                    {
+                                        let message = self.lidar_calibration.stream_message(stream);
+
+                                        self.send_command(&message);
+                                    }
+                                }
+                            });
+                            if self.lidar_calibration.stream != RAW {
+                                ui.colored_label(
+                                    Color32::YELLOW,
+                                    "Capture calibration points from RAW readings.",
+                                );
+                            }
+
+                            ui.horizontal(|ui| {
+                                ui.label("Known distance");
+                                ui.add(egui::DragValue::new(&mut self.lidar_calibration.target));
+                                ui.label("Samples");
+                                ui.add(
+                                    egui::DragValue::new(
+                                        &mut self.lidar_calibration.samples_per_point,
+                                    )
+                                    .range(1..=1000),
+                                );
+                                match &self.lidar_calibration.capture {
+                                    Some(samples) => {
+                                        ui.label(format!(
+                                            "Capturing {}/{}",
+                                            samples.len(),
+                                            self.lidar_calibration.samples_per_point
+                                        ));
+                                    }
+                                    None => {
+                                        if ui.button("Capture").clicked() {
+                                            self.lidar_calibration.start_capture();
+                                        }
+                                    }
+                                }
+                            });
+
+                            let residuals = self.lidar_calibration.residuals();
+                            let mut remove = None;
+                            egui::Grid::new("Lidar Calibration Points")
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    ui.label("Known");
+                                    ui.label("Measured");
+                                    ui.label("Residual");
+                                    ui.label("");
+                                    ui.end_row();
+                                    for (i, point) in
+                                        self.lidar_calibration.points.iter().enumerate()
+                                    {
+                                        ui.label(format!("{:.2}", point.known));
+                                        ui.label(format!("{:.2}", point.measured));
+                                        ui.label(format!("{:.3}", residuals[i]));
+                                        if ui.small_button("x").clicked() {
+                                            remove = Some(i);
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                            if let Some(i) = remove {
+                                self.lidar_calibration.points.remove(i);
+                            }
+
+                            ui.horizontal(|ui| {
+                                ui.label("Degree");
+                                ui.add(
+                                    egui::DragValue::new(&mut self.lidar_calibration.degree)
+                                        .range(1..=MAX_DEGREE),
+                                );
+                                if ui.button("Fit").clicked() {
+                                    self.lidar_calibration.fit();
+                                }
+                                if ui.button("Send Coefficients").clicked() {
+                                    let message = self.lidar_calibration.coefficients_message();
+
+                                    self.send_command(&message);
+                                }
+                            });
+                            ui.label(format!(
+                                "Coefficients: {}",
+                                self.lidar_calibration
+                                    .coefficients
+                                    .iter()
+                                    .map(|c| format!("{:.5}", c))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            ));
+
+                            let measured: Vec<f64> = self
+                                .lidar_calibration
+                                .points
+                                .iter()
+                                .map(|p| p.measured as f64)
+                                .collect();
+                            let low = measured.iter().copied().fold(f64::INFINITY, f64::min);
+                            let high = measured.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                            let fit: PlotPoints = if low < high {
+                                (0..=50)
+                                    .map(|i| {
+                                        let x = low + (high - low) * i as f64 / 50.0;
+                                        [x, self.lidar_calibration.apply(x)]
+                                    })
+                                    .collect()
+                            } else {
+                                PlotPoints::default()
+                            };
+                            let points: PlotPoints = self
+                                .lidar_calibration
+                                .points
+                                .iter()
+                                .map(|p| [p.measured as f64, p.known as f64])
+                                .collect();
+
+                            Plot::new("Lidar Calibration Plot")
+                                .height(150.0)
+                                .legend(Legend::default())
+                                .show(ui, |plot_ui| {
+                                    plot_ui.points(
+                                        Points::new("Known vs Measured", points).radius(3.0),
+                                    );
+                                    plot_ui.line(Line::new("Correction", fit));
+                                });
+
+                            ui.horizontal(|ui| {
+                                ui.text_edit_singleline(&mut self.lidar_calibration.path);
+                                if ui.button("Save").clicked() {
+                                    self.lidar_calibration.save();
+                                }
+                                if ui.button("Load").clicked() {
+                                    self.lidar_calibration.load();
+                                }
+                            });
+                            ui.label(&self.lidar_calibration.status);
+                        });
+                    });
+                }
+                View::Magnetometer => {
+                    let readings: Vec<[f32; 3]> =
+                        self.magnetometer.readings.iter().copied().collect();
+                    let project = |i: usize, j: usize| -> PlotPoints {
+                        readings
+                            .iter()
+                            .map(|r| [r[i] as f64, r[j] as f64])
+                            .collect()
+                    };
+                    let corrected: PlotPoints = readings
+                        .iter()
+                        .map(|r| {
+                            let [x, y] = self.magnetometer.calibration.apply(*r);
+                            [x as f64, y as f64]
+                        })
+                        .collect();
+                    let collected: PlotPoints = self
+                        .magnetometer
+                        .calibration_samples
+                        .iter()
+                        .flatten()
+                        .map(|r| [r[0] as f64, r[1] as f64])
+                        .collect();
+
+                    let heading_series = |index: usize| -> PlotPoints {
+                        (0..self.magnetometer.heading_history.len())
+                            .map(|i| {
+                                let v = self.magnetometer.heading_history.get(i).unwrap()[index];
+                                [i as f64, v.to_degrees()]
+                            })
+                            .collect()
+                    };
+
+                    let height = ui.available_height() * 0.6;
+
+                    ui.columns(2, |columns| {
+                        Plot::new("Magnetometer Scatter")
+                            .height(height)
+                            .data_aspect(1.0)
+                            .legend(Legend::default())
+                            .show(&mut columns[0], |plot_ui| {
+                                plot_ui.points(Points::new("X/Y", project(0, 1)).radius(1.5));
+                                plot_ui.points(Points::new("X/Z", project(0, 2)).radius(1.5));
+                                plot_ui.points(Points::new("Y/Z", project(1, 2)).radius(1.5));
+                                plot_ui.points(
+                                    Points::new("Corrected X/Y", corrected)
+                                        .radius(1.5)
+                                        .color(Color32::GREEN),
+                                );
+                                plot_ui.points(
+                                    Points::new("Calibration Samples", collected)
+                                        .radius(2.5)
+                                        .color(Color32::YELLOW),
+                                );
+                            });
+
+                        Plot::new("Heading Plot")
+                            .height(height)
+                            .include_y(-180.0)
+                            .include_y(180.0)
+                            .legend(Legend::default())
+                            .show(&mut columns[1], |plot_ui| {
+                                plot_ui.line(Line::new("Magnetic Heading", heading_series(0)));
+                                plot_ui.line(Line::new("Odometry Theta", heading_series(1)));
+                            });
+                    });
+
+                    if let Some(reading) = self.magnetometer.latest {
+                        ui.label(format!(
+                            "Raw ({:.2}, {:.2}, {:.2}), heading {:.1}°",
+                            reading[0],
+                            reading[1],
+                            reading[2],
+                            self.magnetometer.calibration.heading(reading).to_degrees()
+                        ));
+                    } else {
+                        ui.label("No MAGNETOMETER telemetry received.");
+                    }
+                    if let Some(pos) = self.position_histogram.last() {
+                        ui.label(format!("Odometry theta {:.1}°", pos.theta.to_degrees()));
+                    }
+
+                    ui.horizontal(|ui| {
+                        let collecting = self.magnetometer.calibration_samples.is_some();
+                        ui.add_enabled(
+                            !collecting,
+                            egui::Checkbox::new(&mut self.magnetometer.spin, "Spin robot"),
+                        );
+                        ui.add_enabled(
+                            !collecting,
+                            egui::DragValue::new(&mut self.magnetometer.spin_speed)
+                                .speed(0.01)
+                                .prefix("speed: "),
+                        );
+
+                        match &self.magnetometer.calibration_samples {
+                            Some(samples) => {
+                                // The stop goes out even while latched.
+                                if ui
+                                    .button(format!("Finish Calibration ({})", samples.len()))
+                                    .clicked()
+                                    && let Some(message) = self.magnetometer.finish_calibration()
+                                    && let Some(port) = self.port.as_mut()
+                                {
+                                    send_message(port, &message);
+                                }
+                            }
+                            None => {
+                                if ui
+                                    .add_enabled(
+                                        !self.safety.estopped,
+                                        egui::Button::new("Start Calibration"),
+                                    )
+                                    .clicked()
+                                    && let Some(message) = self.magnetometer.start_calibration()
+                                {
+                                    self.send_command(&message);
+                                }
+                            }
+                        }
+
+                        if ui.button("Align Heading to Odometry").clicked()
+                            && let Some(pos) = self.position_histogram.last()
+                        {
+                            self.magnetometer.align_to(pos.theta);
+                        }
+
+                        if ui.button("Send Calibration").clicked() {
+                            let message = self.magnetometer.calibration_message();
+
+                            self.send_command(&message);
+                        }
+                    });
+
+                    let c = &self.magnetometer.calibration;
+                    ui.label(format!(
+                        "Offset ({:.3}, {:.3}), soft iron [[{:.4}, {:.4}], [{:.4}, {:.4}]], \
+                         heading offset {:.1}°",
+                        c.offset[0],
+                        c.offset[1],
+                        c.soft_iron[0][0],
+                        c.soft_iron[0][1],
+                        c.soft_iron[1][0],
+                        c.soft_iron[1][1],
+                        c.heading_offset.to_degrees()
+                    ));
+                    ui.label(&self.magnetometer.status);
+                }
+                View::IrBeacon => {
+                    let bars: Vec<Bar> = self
+                        .ir_beacon
+                        .strengths
+                        .iter()
+                        .enumerate()
+                        .map(|(i, s)| {
+                            let name = match self.ir_beacon.receiver_angles.get(i) {
+                                Some(angle) => format!("Receiver {} ({:.0}°)", i, angle),
+                                None => format!("Receiver {}", i),
+                            };
+                            Bar::new(i as f64, *s as f64).name(name)
+                        })
+                        .collect();
+
+                    let receiver_count = self
+                        .ir_beacon
+                        .history
+                        .iter()
+                        .map(|(_, s)| s.len())
+                        .max()
+                        .unwrap_or(0);
+                    let strength_lines: Vec<Line> = (0..receiver_count)
+                        .map(|r| {
+                            let points: PlotPoints = self
+                                .ir_beacon
+                                .history
+                                .iter()
+                                .filter_map(|(i, s)| s.get(r).map(|v| [*i as f64, *v as f64]))
+                                .collect();
+                            Line::new(format!("Receiver {}", r), points)
+                        })
+                        .collect();
+                    let bearings: PlotPoints =
+                        self.ir_beacon.bearing_history.iter().copied().collect();
+
+                    let height = ui.available_height() * 0.45;
+
+                    ui.columns(2, |columns| {
+                        let ui = &mut columns[0];
+                        Plot::new("Beacon Strength Bars")
+                            .height(height)
+                            .legend(Legend::default())
+                            .show(ui, |plot_ui| {
+                                plot_ui.bar_chart(BarChart::new("Strength", bars));
+                            });
+                        ir_beacon::compass(ui, &self.ir_beacon, height.min(250.0));
+
+                        let ui = &mut columns[1];
+                        Plot::new("Beacon Strength History")
+                            .height(height)
+                            .legend(Legend::default())
+                            .show(ui, |plot_ui| {
+                                for line in strength_lines {
+                                    plot_ui.line(line);
+                                }
+                            });
+                        Plot::new("Beacon Bearing History")
+                            .height(height * 0.6)
+                            .include_y(-180.0)
+                            .include_y(180.0)
+                            .show(ui, |plot_ui| {
+                                plot_ui.points(
+                                    Points::new("Bearing", bearings)
+                                        .radius(1.5)
+                                        .color(Color32::RED),
+                                );
+                            });
+                    });
+
+                    match self.ir_beacon.bearing() {
+                        Some(bearing) => ui.label(format!("Bearing {:.1}°", bearing.to_degrees())),
+                        None => ui.label("No bearing, signal too weak."),
+                    };
+                    if !self.ir_beacon.status.is_empty() {
+                        ui.colored_label(Color32::YELLOW, &self.ir_beacon.status);
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("Min strength");
+                        ui.add(egui::DragValue::new(&mut self.ir_beacon.min_strength).speed(0.01));
+                        ui.checkbox(&mut self.ir_beacon.show_on_map, "Show on odometry map");
+                        ui.label("Line length");
+                        ui.add(
+                            egui::DragValue::new(&mut self.ir_beacon.line_length)
+                                .speed(0.05)
+                                .suffix(" m"),
+                        );
+                        if ui.button("Mark Bearing").clicked()
+                            && let Some(line) = self
+                                .position_histogram
+                                .last()
+                                .and_then(|pos| self.ir_beacon.line_of_bearing(pos))
+                        {
+                            self.ir_beacon.fixes.push(line);
+                        }
+                        if ui.button("Clear Marks").clicked() {
+                            self.ir_beacon.fixes.clear();
+                        }
+                    });
+
+                    ui.collapsing("Receiver Angles", |ui| {
+                        for (i, angle) in self.ir_beacon.receiver_angles.iter_mut().enumerate() {
+                            ui.add(
+                                egui::DragValue::new(angle)
+                                    .prefix(format!("Receiver {}: ", i))
+                                    .suffix("°"),
+                            );
+                        }
+                        if ui.button("Space Evenly").clicked() {
+                            self.ir_beacon.space_evenly(self.ir_beacon.strengths.len());
+                        }
+                    });
+                }
+                View::TapeSensor => {
+                    let raw_bars: Vec<Bar> = self
+                        .tape_sensor
+                        .raw
+                        .iter()
+                        .enumerate()
+                        .map(|(i, v)| Bar::new(i as f64, *v as f64).width(0.7))
+                        .collect();
+                    let converted_bars: Vec<Bar> = self
+                        .tape_sensor
+                        .converted
+                        .iter()
+                        .enumerate()
+                        .map(|(i, v)| Bar::new(i as f64, *v as f64).width(0.7))
+                        .collect();
+                    let threshold_lines: Vec<Vec<[f64; 2]>> = self
+                        .tape_sensor
+                        .thresholds
+                        .iter()
+                        .enumerate()
+                        .map(|(i, t)| {
+                            vec![[i as f64 - 0.4, *t as f64], [i as f64 + 0.4, *t as f64]]
+                        })
+                        .collect();
+                    let positions: PlotPoints =
+                        self.tape_sensor.position_history.iter().copied().collect();
+
+                    let height = ui.available_height() * 0.35;
+
+                    ui.columns(2, |columns| {
+                        Plot::new("Tape Raw Bars")
+                            .height(height)
+                            .legend(Legend::default())
+                            .show(&mut columns[0], |plot_ui| {
+                                plot_ui.bar_chart(BarChart::new("Raw", raw_bars));
+                                for line in threshold_lines {
+                                    plot_ui.line(
+                                        Line::new("Threshold", line).color(Color32::RED).width(2.0),
+                                    );
+                                }
+                            });
+
+                        Plot::new("Tape Converted Bars")
+                            .height(height)
+                            .legend(Legend::default())
+                            .show(&mut columns[1], |plot_ui| {
+                                plot_ui.bar_chart(
+                                    BarChart::new("Converted", converted_bars)
+                                        .color(Color32::LIGHT_GREEN),
+                                );
+                            });
+                    });
+
+                    Plot::new("Line Position Plot")
                         .height(height)
                         .legend(Legend::default())
                         .show(ui, |plot_ui| {
-                            plot_ui.line(Line::new("Distance", lidar_distance));
-                            plot_ui.line(Line::new("Convolution", lidar_convolution));
+                            plot_ui.line(Line::new("Line Position", positions));
+                            plot_ui
+                                .hline(HLine::new("Centre", 0.0).style(LineStyle::dashed_loose()));
                         });
 
-                    Plot::new("log plot")
+                    match self.tape_sensor.line_position() {
+                        Some(position) => ui.label(format!(
+                            "Line {:.1} mm {} of centre",
+                            position.abs() * 1000.0,
+                            if position >= 0.0 { "left" } else { "right" }
+                        )),
+                        None => ui.label("Line lost."),
+                    };
+
+                    ui.horizontal(|ui| {
+                        ui.label("Sensor spacing");
+                        ui.add(
+                            egui::DragValue::new(&mut self.tape_sensor.spacing)
+                                .speed(0.001)
+                                .suffix(" m"),
+                        );
+                    });
+
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label("Thresholds");
+                        for (i, threshold) in self.tape_sensor.thresholds.iter_mut().enumerate() {
+                            ui.add(egui::DragValue::new(threshold).prefix(format!("{}: ", i)));
+                        }
+                        if ui
+                            .add_enabled(
+                                !self.tape_sensor.thresholds.is_empty(),
+                                egui::Button::new("Send Thresholds"),
+                            )
+                            .clicked()
+                        {
+                            let message = self.tape_sensor.thresholds_message();
+
+                            self.send_command(&message);
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Line following gains");
+                        ui.add(
+                            egui::DragValue::new(&mut self.tape_sensor.kp)
+                                .speed(0.01)
+                                .prefix("kp: "),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut self.tape_sensor.ki)
+                                .speed(0.01)
+                                .prefix("ki: "),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut self.tape_sensor.kd)
+                                .speed(0.01)
+                                .prefix("kd: "),
+                        );
+                        if ui.button("Send Gains").clicked() {
+                            let message = self.tape_sensor.gains_message();
+
+                            self.send_command(&message);
+                        }
+                    });
+                }
+                View::Encoder => {
+                    if let Some(messages) = self.encoder.poll() {
+                        for message in &messages {
+                            self.send_command(message);
+                        }
+                    }
+
+                    let unit = if self.encoder.units == RAW {
+                        "ticks"
+                    } else {
+                        "°"
+                    };
+                    let angles: PlotPoints = self.encoder.angle_history.iter().copied().collect();
+                    let velocities: PlotPoints =
+                        self.encoder.velocity_history.iter().copied().collect();
+
+                    let height = ui.available_height() * 0.35;
+
+                    Plot::new("Encoder Angle Plot")
+                        .height(height)
+                        .legend(Legend::default())
+                        .show(ui, |plot_ui| {
+                            plot_ui.line(Line::new(format!("Angle ({})", unit), angles));
+                        });
+
+                    Plot::new("Encoder Velocity Plot")
                         .height(height)
                         .legend(Legend::default())
                         .show(ui, |plot_ui| {
-                            plot_ui.line(Line::new("Distance", lidar_distance_log));
-                            plot_ui.line(Line::new("Convolution", lidar_convolution_log));
+                            plot_ui.line(Line::new(format!("Velocity ({}/s)", unit), velocities));
                         });
 
-                    if ui.button("Clear Log").clicked() {
-                        self.lidar_distance_log.clear();
-                        self.lidar_convolution_log.clear();
+                    ui.horizontal(|ui| {
+                        for units in [RAW, CONVERTED] {
+                            if ui
+                                .add(egui::RadioButton::new(
+                                    self.encoder.units == units,
+                                    format!("{:?}", units),
+                                ))
+                                .clicked()
+                            {
+                                let message = self.encoder.units_message(units);
+
+                                self.send_command(&message);
+                            }
+                        }
+
+                        ui.checkbox(&mut self.encoder.polling, "Poll every");
+                        ui.add(
+                            egui::DragValue::new(&mut self.encoder.poll_interval_ms)
+                                .range(10..=1000)
+                                .suffix(" ms"),
+                        );
+                    });
+
+                    match (self.encoder.angle, self.encoder.velocity) {
+                        (Some(angle), velocity) => {
+                            let mut text = format!("Angle {:.2} {}", angle, unit);
+                            if let Some(velocity) = velocity {
+                                text += &format!(", velocity {:.2} {}/s", velocity, unit);
+                            }
+                            if self.encoder.units == RAW {
+                                text += &format!(" ({:.1}°)", self.encoder.to_degrees(angle));
+                            }
+                            ui.label(text);
+                        }
+                        (None, _) => {
+                            ui.label("No ENCODER_MOTOR telemetry received.");
+                        }
                     }
+
+                    ui.collapsing("Counts per Revolution", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Turns");
+                            ui.add(
+                                egui::DragValue::new(&mut self.encoder.calibration_turns)
+                                    .speed(0.1),
+                            );
+                            if self.encoder.calibration_start.is_some() {
+                                if ui.button("Finish").clicked() {
+                                    self.encoder.finish_calibration();
+                                }
+                            } else if ui.button("Start").clicked() {
+                                self.encoder.start_calibration();
+                            }
+                        });
+                        if let (Some(start), Some(angle)) =
+                            (self.encoder.calibration_start, self.encoder.angle)
+                        {
+                            ui.label(format!("{:.0} counts so far.", (angle - start).abs()));
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Counts per revolution");
+                            ui.add(
+                                egui::DragValue::new(&mut self.encoder.counts_per_rev)
+                                    .range(f32::EPSILON..=f32::MAX),
+                            );
+                            if ui.button("Send").clicked()
+                                && let Some(message) = self.encoder.counts_per_rev_message()
+                            {
+                                self.send_command(&message);
+                            }
+                        });
+                        ui.label(&self.encoder.status);
+                    });
                 }
             }
         });
@@ -0,0 +1,497 @@
+use std::f32::consts::PI;
+use std::ops::Range;
+
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+#[derive(Debug)]
+pub struct Pos {
+    pub x: f32,
+    pub y: f32,
+    pub theta: f32,
+    /// Seconds since the panel started, when the sample was received.
+    pub t: f32,
+}
+
+/// Wrap an angle in radians into [-π, π]. Non-finite angles give NaN.
+pub fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// What a click on the odometry plot does.
+#[derive(PartialEq, Clone, Copy)]
+pub enum OdoPlotTool {
+    Pan,
+    SetOrigin,
+    AlignMap,
+    AddWaypoint,
+    SetPose,
+}
+
+/// User-chosen frame the odometry traces are drawn in.
+///
+/// Samples are translated so `origin` sits at (0, 0), rotated by `angle`
+/// (plus the negated starting heading when `align_to_start` is set) and then
+/// optionally mirrored to match the field orientation.
+pub struct PlotFrame {
+    /// Rotation in degrees, counter-clockwise.
+    pub angle: f32,
+    /// Origin in raw odometry coordinates (metres).
+    pub origin: [f64; 2],
+    pub align_to_start: bool,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl PlotFrame {
+    pub fn new() -> Self {
+        Self {
+            angle: 0.0,
+            origin: [0.0, 0.0],
+            align_to_start: false,
+            flip_x: false,
+            flip_y: false,
+        }
+    }
+
+    /// Resolve the frame against the first sample of the run.
+    pub fn transform(&self, start: Option<&Pos>) -> FrameTransform {
+        let mut angle = (self.angle as f64).to_radians();
+        if self.align_to_start
+            && let Some(start) = start
+        {
+            angle -= start.theta as f64;
+        }
+
+        FrameTransform {
+            cos: angle.cos(),
+            sin: angle.sin(),
+            origin: self.origin,
+            flip_x: self.flip_x,
+            flip_y: self.flip_y,
+        }
+    }
+}
+
+pub struct FrameTransform {
+    cos: f64,
+    sin: f64,
+    origin: [f64; 2],
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl FrameTransform {
+    /// Raw odometry coordinates to plot coordinates.
+    pub fn apply(&self, x: f64, y: f64) -> [f64; 2] {
+        let dx = x - self.origin[0];
+        let dy = y - self.origin[1];
+        let rx = self.cos * dx - self.sin * dy;
+        let ry = self.sin * dx + self.cos * dy;
+        [
+            if self.flip_x { -rx } else { rx },
+            if self.flip_y { -ry } else { ry },
+        ]
+    }
+
+    /// Plot coordinates back to raw odometry coordinates.
+    pub fn invert(&self, x: f64, y: f64) -> [f64; 2] {
+        let rx = if self.flip_x { -x } else { x };
+        let ry = if self.flip_y { -y } else { y };
+        [
+            self.cos * rx + self.sin * ry + self.origin[0],
+            -self.sin * rx + self.cos * ry + self.origin[1],
+        ]
+    }
+}
+
+/// Physical layout of the robot in its own frame (x forward, y left, metres),
+/// measured from the drive axle centre that odometry reports.
+pub struct RobotConfig {
+    /// Distance from the axle to the point traced as "Front Position".
+    pub front_offset: f32,
+    /// Distance from the axle to the back of the chassis.
+    pub rear_offset: f32,
+    /// Distance between the drive wheels.
+    pub wheelbase: f32,
+    /// Location of the arm turntable.
+    pub arm_position: [f32; 2],
+}
+
+impl RobotConfig {
+    pub fn new() -> Self {
+        Self {
+            front_offset: 0.235,
+            rear_offset: 0.05,
+            wheelbase: 0.22,
+            arm_position: [0.1, 0.0],
+        }
+    }
+
+    /// Robot-frame point to raw odometry coordinates.
+    pub fn to_world(&self, pos: &Pos, local: [f32; 2]) -> [f64; 2] {
+        let (sin, cos) = pos.theta.sin_cos();
+        [
+            (pos.x + cos * local[0] - sin * local[1]) as f64,
+            (pos.y + sin * local[0] + cos * local[1]) as f64,
+        ]
+    }
+
+    pub fn front_point(&self, pos: &Pos) -> Pos {
+        let [x, y] = self.to_world(pos, [self.front_offset, 0.0]);
+        Pos {
+            x: x as f32,
+            y: y as f32,
+            theta: pos.theta,
+            t: pos.t,
+        }
+    }
+
+    /// Closed outline of the chassis, in raw odometry coordinates.
+    pub fn footprint(&self, pos: &Pos) -> Vec<[f64; 2]> {
+        let half_width = 0.5 * self.wheelbase;
+        [
+            [self.front_offset, half_width],
+            [-self.rear_offset, half_width],
+            [-self.rear_offset, -half_width],
+            [self.front_offset, -half_width],
+            [self.front_offset, half_width],
+        ]
+        .iter()
+        .map(|p| self.to_world(pos, *p))
+        .collect()
+    }
+}
+
+/// Split a history of `len` samples into the segments that start at
+/// `starts`, so pose resets don't draw a jump line.
+pub fn segment_ranges(len: usize, starts: &[usize]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::with_capacity(starts.len() + 1);
+    let mut begin = 0;
+    for &start in starts.iter().filter(|&&s| s > 0 && s < len) {
+        ranges.push(begin..start);
+        begin = start;
+    }
+    ranges.push(begin..len);
+    ranges
+}
+
+/// Pose typed or dragged in the panel, to be sent with `ODOMETRY SET`.
+pub struct PoseSetter {
+    pub x: f32,
+    pub y: f32,
+    /// Heading in degrees.
+    pub theta: f32,
+    /// Where a drag on the plot started, in raw odometry coordinates.
+    pub drag_start: Option<[f64; 2]>,
+}
+
+impl PoseSetter {
+    pub fn new() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            theta: 0.0,
+            drag_start: None,
+        }
+    }
+
+    pub fn pos(&self) -> Pos {
+        Pos {
+            x: self.x,
+            y: self.y,
+            theta: self.theta.to_radians(),
+            t: 0.0,
+        }
+    }
+
+    /// Place the robot at the drag start, facing towards `to`.
+    pub fn drag_to(&mut self, to: [f64; 2]) {
+        if let Some(from) = self.drag_start {
+            self.x = from[0] as f32;
+            self.y = from[1] as f32;
+            if (to[0] - from[0]).hypot(to[1] - from[1]) > 1e-3 {
+                self.theta = (to[1] - from[1]).atan2(to[0] - from[0]).to_degrees() as f32;
+            }
+        }
+    }
+}
+
+/// `ODOMETRY SET ANGLE <x> <y> <theta>`. The `ANGLE` sub-code keeps it apart
+/// from the `ODOMETRY SET <index> <x> <y>` waypoints, which are the same
+/// length on the wire.
+pub fn set_pose_message(pos: &Pos) -> [MsgElem; 6] {
+    [
+        Code(ODOMETRY),
+        Code(SET),
+        Code(ANGLE),
+        F32(pos.x),
+        F32(pos.y),
+        F32(pos.theta),
+    ]
+}
+
+/// Summary of one odometry segment.
+pub struct SegmentStats {
+    pub samples: usize,
+    pub duration: f32,
+    /// Path length in metres.
+    pub distance: f32,
+    /// Unwrapped heading change in radians.
+    pub heading_change: f32,
+    /// Straight-line distance between the first and last sample.
+    pub closure_error: f32,
+    pub mean_speed: f32,
+    pub max_speed: f32,
+}
+
+pub fn segment_stats(poses: &[Pos]) -> SegmentStats {
+    let mut distance = 0.0;
+    let mut heading_change = 0.0;
+    let mut max_speed: f32 = 0.0;
+
+    for pair in poses.windows(2) {
+        let ds = (pair[1].x - pair[0].x).hypot(pair[1].y - pair[0].y);
+        let dt = pair[1].t - pair[0].t;
+        distance += ds;
+        heading_change += wrap_angle(pair[1].theta - pair[0].theta);
+        if dt > 0.0 {
+            max_speed = max_speed.max(ds / dt);
+        }
+    }
+
+    let (duration, closure_error) = match (poses.first(), poses.last()) {
+        (Some(first), Some(last)) => (last.t - first.t, (last.x - first.x).hypot(last.y - first.y)),
+        _ => (0.0, 0.0),
+    };
+
+    SegmentStats {
+        samples: poses.len(),
+        duration,
+        distance,
+        heading_change,
+        closure_error,
+        mean_speed: if duration > 0.0 {
+            distance / duration
+        } else {
+            0.0
+        },
+        max_speed,
+    }
+}
+
+/// Stats for a whole run split into `segments`. Distance, heading change and
+/// max speed come from the segments so the jumps at pose resets don't count;
+/// duration and closure error go from the first sample of the run to the last.
+pub fn run_stats(poses: &[Pos], segments: &[Range<usize>]) -> SegmentStats {
+    let whole = segment_stats(poses);
+    let parts: Vec<SegmentStats> = segments
+        .iter()
+        .map(|range| segment_stats(&poses[range.clone()]))
+        .collect();
+    let distance: f32 = parts.iter().map(|s| s.distance).sum();
+
+    SegmentStats {
+        samples: poses.len(),
+        duration: whole.duration,
+        distance,
+        heading_change: parts.iter().map(|s| s.heading_change).sum(),
+        closure_error: whole.closure_error,
+        mean_speed: if whole.duration > 0.0 {
+            distance / whole.duration
+        } else {
+            0.0
+        },
+        max_speed: parts.iter().map(|s| s.max_speed).fold(0.0, f32::max),
+    }
+}
+
+/// Finite-difference rates between consecutive samples.
+pub struct Rates {
+    pub t: f64,
+    pub speed: f64,
+    pub angular_velocity: f64,
+    /// `None` when the robot barely moved, e.g. turning in place.
+    pub curvature: Option<f64>,
+}
+
+pub fn rates(poses: &[Pos]) -> Vec<Rates> {
+    poses
+        .windows(2)
+        .filter(|pair| pair[1].t > pair[0].t)
+        .map(|pair| {
+            let ds = (pair[1].x - pair[0].x).hypot(pair[1].y - pair[0].y) as f64;
+            let dtheta = wrap_angle(pair[1].theta - pair[0].theta) as f64;
+            let dt = (pair[1].t - pair[0].t) as f64;
+            Rates {
+                t: pair[1].t as f64,
+                speed: ds / dt,
+                angular_velocity: dtheta / dt,
+                curvature: if ds > 1e-4 { Some(dtheta / ds) } else { None },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::convert_message;
+
+    fn assert_wrapped(angle: f32, expected: f32) {
+        let wrapped = wrap_angle(angle);
+        assert!(
+            (-PI..=PI).contains(&wrapped) && (wrapped - expected).abs() < 1e-5,
+            "wrap_angle({}) = {}, expected {}",
+            angle,
+            wrapped,
+            expected
+        );
+    }
+
+    #[test]
+    fn wraps_into_range() {
+        assert_wrapped(0.0, 0.0);
+        assert_wrapped(0.5 * PI, 0.5 * PI);
+        assert_wrapped(1.5 * PI, -0.5 * PI);
+        assert_wrapped(-1.5 * PI, 0.5 * PI);
+    }
+
+    #[test]
+    fn odd_multiples_of_pi_wrap_to_the_boundary() {
+        assert!((wrap_angle(3.0 * PI).abs() - PI).abs() < 1e-5);
+        assert!((wrap_angle(-3.0 * PI).abs() - PI).abs() < 1e-5);
+    }
+
+    #[test]
+    fn large_angles_terminate_in_range() {
+        for angle in [1e9, -1e9, f32::MAX, f32::MIN] {
+            assert!((-PI..=PI).contains(&wrap_angle(angle)), "{}", angle);
+        }
+    }
+
+    #[test]
+    fn infinite_angles_are_nan() {
+        assert!(wrap_angle(f32::INFINITY).is_nan());
+        assert!(wrap_angle(f32::NEG_INFINITY).is_nan());
+        assert!(wrap_angle(f32::NAN).is_nan());
+    }
+
+    #[test]
+    fn set_pose_differs_from_a_waypoint_on_the_wire() {
+        let pos = Pos {
+            x: 1.0,
+            y: 2.0,
+            theta: 0.5,
+            t: 0.0,
+        };
+        // Values carry no type tag, so without a sub-code this waypoint would
+        // encode to exactly the same bytes as the pose.
+        let waypoint = [
+            Code(ODOMETRY),
+            Code(SET),
+            U32(pos.x.to_bits()),
+            F32(pos.y),
+            F32(pos.theta),
+        ];
+        assert_ne!(
+            convert_message(&set_pose_message(&pos)),
+            convert_message(&waypoint)
+        );
+    }
+
+    fn pose(x: f32, y: f32, theta: f32, t: f32) -> Pos {
+        Pos { x, y, theta, t }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn segment_stats_unwraps_heading_across_pi() {
+        // Quarter turns counter-clockwise around a unit square, crossing ±π.
+        let poses = [
+            pose(0.0, 0.0, 0.5 * PI, 0.0),
+            pose(0.0, 1.0, PI, 1.0),
+            pose(-1.0, 1.0, -0.5 * PI, 2.0),
+            pose(-1.0, 0.0, 0.0, 3.0),
+            pose(0.0, 0.0, 0.5 * PI, 4.0),
+        ];
+        let stats = segment_stats(&poses);
+
+        assert_eq!(stats.samples, 5);
+        assert!(close(stats.duration, 4.0));
+        assert!(close(stats.distance, 4.0));
+        assert!(
+            close(stats.heading_change, 2.0 * PI),
+            "{}",
+            stats.heading_change
+        );
+        assert!(close(stats.closure_error, 0.0));
+        assert!(close(stats.mean_speed, 1.0));
+        assert!(close(stats.max_speed, 1.0));
+    }
+
+    #[test]
+    fn repeated_timestamps_dont_produce_infinite_rates() {
+        let poses = [
+            pose(0.0, 0.0, 0.0, 0.0),
+            pose(0.5, 0.0, 0.0, 0.5),
+            // Same timestamp as the previous sample.
+            pose(0.6, 0.0, 0.1, 0.5),
+            pose(1.0, 0.0, 0.1, 1.0),
+        ];
+
+        let stats = segment_stats(&poses);
+        assert!(stats.max_speed.is_finite());
+        assert!(close(stats.distance, 1.0));
+
+        let rates = rates(&poses);
+        assert_eq!(rates.len(), 2);
+        assert!(rates
+            .iter()
+            .all(|r| r.speed.is_finite() && r.angular_velocity.is_finite()));
+        assert!((rates[0].speed - 1.0).abs() < 1e-5);
+        assert!((rates[1].t - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rates_across_the_wrap_and_turning_in_place() {
+        let poses = [
+            pose(0.0, 0.0, PI - 0.1, 0.0),
+            pose(0.0, 0.0, -PI + 0.1, 0.5),
+            pose(1.0, 0.0, -PI + 0.6, 1.5),
+        ];
+        let rates = rates(&poses);
+
+        // Turning in place: 0.2 rad in 0.5 s, no curvature.
+        assert!((rates[0].angular_velocity - 0.4).abs() < 1e-5);
+        assert!(rates[0].speed.abs() < 1e-9);
+        assert!(rates[0].curvature.is_none());
+
+        assert!((rates[1].speed - 1.0).abs() < 1e-5);
+        assert!((rates[1].curvature.unwrap() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn run_stats_skip_the_jump_at_a_pose_reset() {
+        let poses = [
+            pose(0.0, 0.0, 0.0, 0.0),
+            pose(1.0, 0.0, 0.0, 1.0),
+            // Pose reset back to the origin.
+            pose(0.0, 0.0, 0.0, 2.0),
+            pose(0.0, 2.0, 0.5, 3.0),
+        ];
+        let segments = segment_ranges(poses.len(), &[2]);
+        let stats = run_stats(&poses, &segments);
+
+        assert_eq!(stats.samples, 4);
+        assert!(close(stats.duration, 3.0));
+        assert!(close(stats.distance, 3.0));
+        assert!(close(stats.heading_change, 0.5));
+        assert!(close(stats.closure_error, 2.0));
+        assert!(close(stats.mean_speed, 1.0));
+        assert!(close(stats.max_speed, 2.0));
+    }
+}
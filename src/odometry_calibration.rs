@@ -0,0 +1,289 @@
+//! Guided wheel radius / track width calibration.
+//!
+//! The robot drives a straight line and then turns in place using
+//! `DRIVE_BASE SET` commands, stopping once odometry says the target has been
+//! reached. The user then measures what actually happened on the floor and
+//! the ratio between the two gives the correction factors.
+
+use std::time::{Duration, Instant};
+
+use crate::odometry::{wrap_angle, Pos};
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+/// Stop a test run if it hasn't reached its target in this long.
+const RUN_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(PartialEq)]
+pub enum CalibrationStep {
+    Idle,
+    Straight,
+    MeasureStraight,
+    Rotation,
+    MeasureRotation,
+    Done,
+}
+
+pub struct CalibrationWizard {
+    pub step: CalibrationStep,
+
+    /// Straight run length in metres.
+    pub target_distance: f32,
+    /// In-place rotation in degrees.
+    pub target_angle: f32,
+    pub drive_speed: f32,
+    pub turn_speed: f32,
+
+    /// Ground truth typed in by the user.
+    pub measured_distance: f32,
+    pub measured_angle: f32,
+
+    /// What odometry reported for each run.
+    pub odo_distance: f32,
+    pub odo_angle: f32,
+
+    pub wheel_scale: f32,
+    pub track_scale: f32,
+    pub status: String,
+
+    start: [f32; 2],
+    last_theta: f32,
+    started: Instant,
+}
+
+impl CalibrationWizard {
+    pub fn new() -> Self {
+        Self {
+            step: CalibrationStep::Idle,
+            target_distance: 1.0,
+            target_angle: 360.0,
+            drive_speed: 0.2,
+            turn_speed: 0.15,
+            measured_distance: 1.0,
+            measured_angle: 360.0,
+            odo_distance: 0.0,
+            odo_angle: 0.0,
+            wheel_scale: 1.0,
+            track_scale: 1.0,
+            status: String::new(),
+            start: [0.0, 0.0],
+            last_theta: 0.0,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn start_straight(&mut self, pos: &Pos) -> Vec<MsgElem> {
+        self.step = CalibrationStep::Straight;
+        self.start = [pos.x, pos.y];
+        self.odo_distance = 0.0;
+        self.started = Instant::now();
+        self.status.clear();
+        vec![Code(DRIVE_BASE), Code(SET), F32(self.drive_speed), U32(0)]
+    }
+
+    pub fn start_rotation(&mut self, pos: &Pos) -> Vec<MsgElem> {
+        self.step = CalibrationStep::Rotation;
+        self.last_theta = pos.theta;
+        self.odo_angle = 0.0;
+        self.started = Instant::now();
+        self.status.clear();
+        vec![
+            Code(DRIVE_BASE),
+            Code(SET),
+            Code(LEFT),
+            F32(-self.turn_speed),
+            Code(RIGHT),
+            F32(self.turn_speed),
+        ]
+    }
+
+    /// Track progress of the current run. Returns the stop command once
+    /// odometry says the target has been reached, prefilling the measurement
+    /// with the target.
+    pub fn update(&mut self, pos: &Pos) -> Option<Vec<MsgElem>> {
+        match self.step {
+            CalibrationStep::Straight => {
+                self.odo_distance = (pos.x - self.start[0]).hypot(pos.y - self.start[1]);
+                if self.odo_distance < self.target_distance {
+                    return None;
+                }
+                self.measured_distance = self.target_distance;
+                self.step = CalibrationStep::MeasureStraight;
+            }
+            CalibrationStep::Rotation => {
+                let delta = wrap_angle(pos.theta - self.last_theta);
+                self.last_theta = pos.theta;
+                self.odo_angle += delta.to_degrees();
+
+                if self.odo_angle.abs() < self.target_angle {
+                    return None;
+                }
+                self.measured_angle = self.target_angle;
+                self.step = CalibrationStep::MeasureRotation;
+            }
+            _ => return None,
+        }
+        Some(stop_message())
+    }
+
+    /// Called every frame so a run stops even if odometry telemetry doesn't
+    /// arrive. Returns the stop command once the run has timed out, leaving
+    /// the measurement unset.
+    pub fn tick(&mut self, now: Instant) -> Option<Vec<MsgElem>> {
+        if now.duration_since(self.started) <= RUN_TIMEOUT {
+            return None;
+        }
+        match self.step {
+            CalibrationStep::Straight => {
+                self.measured_distance = 0.0;
+                self.step = CalibrationStep::MeasureStraight;
+            }
+            CalibrationStep::Rotation => {
+                self.measured_angle = 0.0;
+                self.step = CalibrationStep::MeasureRotation;
+            }
+            _ => return None,
+        }
+        self.status = self.timeout_status();
+        Some(stop_message())
+    }
+
+    fn timeout_status(&self) -> String {
+        format!(
+            "Timed out after {} s before reaching the target, measure what actually happened.",
+            RUN_TIMEOUT.as_secs()
+        )
+    }
+
+    pub fn abort(&mut self) -> Vec<MsgElem> {
+        self.step = CalibrationStep::Idle;
+        self.status.clear();
+        stop_message()
+    }
+
+    /// Compute the correction factors from the measured ground truth.
+    ///
+    /// Distances scale directly with wheel radius. The heading odometry
+    /// reports is `(d_r - d_l) / track`, so once the distances are corrected
+    /// the track width has to grow by the remaining angle ratio.
+    pub fn compute(&mut self) {
+        if self.odo_distance > 0.0 && self.measured_distance > 0.0 {
+            self.wheel_scale = self.measured_distance / self.odo_distance;
+        }
+        if self.measured_angle.abs() > 0.0 {
+            self.track_scale = self.wheel_scale * self.odo_angle.abs() / self.measured_angle.abs();
+        }
+        self.step = CalibrationStep::Done;
+    }
+
+    pub fn calibration_message(&self) -> [MsgElem; 5] {
+        [
+            Code(DRIVE_BASE),
+            Code(SET),
+            Code(ODOMETRY),
+            F32(self.wheel_scale),
+            F32(self.track_scale),
+        ]
+    }
+}
+
+fn stop_message() -> Vec<MsgElem> {
+    vec![Code(DRIVE_BASE), Code(SET), F32(0.0), U32(0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: f32, y: f32, theta: f32) -> Pos {
+        Pos {
+            x,
+            y,
+            theta,
+            t: 0.0,
+        }
+    }
+
+    #[test]
+    fn straight_run_stops_at_target() {
+        let mut wizard = CalibrationWizard::new();
+        wizard.start_straight(&pos(1.0, 1.0, 0.0));
+
+        assert!(wizard.update(&pos(1.6, 1.0, 0.0)).is_none());
+        assert_eq!(wizard.update(&pos(2.0, 1.0, 0.0)), Some(stop_message()));
+        assert!(wizard.step == CalibrationStep::MeasureStraight);
+        assert_eq!(wizard.measured_distance, wizard.target_distance);
+        assert!((wizard.odo_distance - 1.0).abs() < 1e-6);
+
+        // Further samples don't restart or re-stop the run.
+        assert!(wizard.update(&pos(3.0, 1.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn rotation_run_accumulates_across_the_wrap() {
+        let mut wizard = CalibrationWizard::new();
+        wizard.target_angle = 90.0;
+        wizard.start_rotation(&pos(0.0, 0.0, 3.0));
+
+        // 0.5 rad steps counter-clockwise through +π/-π.
+        let mut stop = None;
+        for i in 1..=4 {
+            stop = wizard.update(&pos(0.0, 0.0, wrap_angle(3.0 + 0.5 * i as f32)));
+            if i < 4 {
+                assert!(stop.is_none(), "stopped after {} steps", i);
+            }
+        }
+        assert_eq!(stop, Some(stop_message()));
+        assert!(wizard.step == CalibrationStep::MeasureRotation);
+        assert!((wizard.odo_angle - 2f32.to_degrees()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn timeout_stops_without_odometry() {
+        let mut wizard = CalibrationWizard::new();
+        assert!(wizard.tick(Instant::now() + 2 * RUN_TIMEOUT).is_none());
+
+        wizard.start_straight(&pos(0.0, 0.0, 0.0));
+        let started = wizard.started;
+        assert!(wizard.tick(started + RUN_TIMEOUT / 2).is_none());
+        assert_eq!(
+            wizard.tick(started + RUN_TIMEOUT + Duration::from_secs(1)),
+            Some(stop_message())
+        );
+        assert!(wizard.step == CalibrationStep::MeasureStraight);
+        assert_eq!(wizard.measured_distance, 0.0);
+        assert!(!wizard.status.is_empty());
+
+        wizard.start_rotation(&pos(0.0, 0.0, 0.0));
+        let started = wizard.started;
+        assert!(wizard.tick(started + 2 * RUN_TIMEOUT).is_some());
+        assert!(wizard.step == CalibrationStep::MeasureRotation);
+        assert_eq!(wizard.measured_angle, 0.0);
+    }
+
+    #[test]
+    fn compute_scales_wheels_then_track() {
+        let mut wizard = CalibrationWizard::new();
+        wizard.odo_distance = 1.0;
+        wizard.measured_distance = 1.1;
+        wizard.odo_angle = -360.0;
+        wizard.measured_angle = 300.0;
+        wizard.compute();
+
+        assert!((wizard.wheel_scale - 1.1).abs() < 1e-6);
+        assert!((wizard.track_scale - 1.1 * 360.0 / 300.0).abs() < 1e-6);
+        assert!(wizard.step == CalibrationStep::Done);
+    }
+
+    #[test]
+    fn compute_keeps_scales_without_measurements() {
+        let mut wizard = CalibrationWizard::new();
+        wizard.odo_distance = 1.0;
+        wizard.measured_distance = 0.0;
+        wizard.measured_angle = 0.0;
+        wizard.compute();
+
+        assert_eq!(wizard.wheel_scale, 1.0);
+        assert_eq!(wizard.track_scale, 1.0);
+    }
+}
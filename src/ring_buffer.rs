@@ -33,6 +33,10 @@ impl<T> RingBuffer<T> {
         self.buffer.truncate(self.capacity);
     }
 
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
     pub fn len(&self) -> usize {
         self.buffer.len()
     }
@@ -0,0 +1,219 @@
+//! Emergency stop and the heartbeat the firmware watchdog listens for.
+//!
+//! The panel sends `NONE <sequence>` every `heartbeat_interval_ms`. The
+//! firmware is expected to echo it back and to halt all motors if it hasn't
+//! seen one within its watchdog timeout, so a crashed panel or a pulled cable
+//! stops the robot.
+//!
+//! Values aren't escaped on the wire, so sequence numbers with a byte equal to
+//! `MSG_END` are skipped; otherwise the firmware would see the frame end
+//! early.
+
+use std::time::{Duration, Instant};
+
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+/// Treat the link as lost if no echo arrived within this many intervals.
+const MISSED_HEARTBEATS: u32 = 3;
+
+#[derive(PartialEq, Debug)]
+pub enum LinkState {
+    Disabled,
+    /// Heartbeats are going out but none has been echoed yet.
+    Waiting,
+    Ok,
+    Lost,
+}
+
+pub struct Safety {
+    pub estopped: bool,
+    /// Motion commands refused since the E-stop was latched.
+    pub refused: u32,
+
+    pub heartbeat_enabled: bool,
+    pub heartbeat_interval_ms: u32,
+    pub sequence: u32,
+    last_heartbeat: Instant,
+    /// Sequence number and arrival time of the last echoed heartbeat.
+    pub last_ack: Option<(u32, Instant)>,
+}
+
+impl Safety {
+    pub fn new() -> Self {
+        Self {
+            estopped: false,
+            refused: 0,
+            heartbeat_enabled: true,
+            heartbeat_interval_ms: 200,
+            sequence: 0,
+            last_heartbeat: Instant::now(),
+            last_ack: None,
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.heartbeat_interval_ms as u64)
+    }
+
+    /// The next heartbeat, once the interval has passed.
+    pub fn heartbeat(&mut self) -> Option<[MsgElem; 2]> {
+        if !self.heartbeat_enabled || self.last_heartbeat.elapsed() < self.interval() {
+            return None;
+        }
+        self.last_heartbeat = Instant::now();
+        self.sequence = next_sequence(self.sequence);
+        Some([Code(NONE), U32(self.sequence)])
+    }
+
+    pub fn ack(&mut self, sequence: u32) {
+        self.last_ack = Some((sequence, Instant::now()));
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        if !self.heartbeat_enabled {
+            return LinkState::Disabled;
+        }
+        match self.last_ack {
+            None => LinkState::Waiting,
+            Some((_, at)) if at.elapsed() > self.interval() * MISSED_HEARTBEATS => LinkState::Lost,
+            Some(_) => LinkState::Ok,
+        }
+    }
+
+    /// Latch the E-stop and return the commands that halt everything:
+    /// `ALL SET NONE` for every actuator, plus an explicit drive base stop in
+    /// case the firmware predates it.
+    pub fn estop(&mut self) -> Vec<Vec<MsgElem>> {
+        self.estopped = true;
+        self.refused = 0;
+        vec![
+            vec![Code(ALL), Code(SET), Code(NONE)],
+            vec![Code(DRIVE_BASE), Code(SET), F32(0.0), U32(0)],
+        ]
+    }
+
+    pub fn release(&mut self) {
+        self.estopped = false;
+        self.refused = 0;
+    }
+
+    /// Whether `message` may go out. Anything that moves the robot is refused
+    /// while the E-stop is latched; reads and configuration always pass.
+    pub fn allows(&mut self, message: &[MsgElem]) -> bool {
+        if self.estopped && moves_robot(message) {
+            self.refused += 1;
+            return false;
+        }
+        true
+    }
+}
+
+/// The sequence number after `sequence` with no byte equal to `MSG_END`.
+fn next_sequence(sequence: u32) -> u32 {
+    let mut next = sequence.wrapping_add(1);
+    while next.to_le_bytes().contains(&(MSG_END as u32 as u8)) {
+        next = next.wrapping_add(1);
+    }
+    next
+}
+
+/// Whether `message` drives an actuator, as opposed to reading telemetry or
+/// changing configuration.
+pub fn moves_robot(message: &[MsgElem]) -> bool {
+    match message {
+        // Odometry scale factors.
+        [Code(DRIVE_BASE), Code(SET), Code(ODOMETRY), ..] => false,
+        [Code(DRIVE_BASE | ARM | CLAW | TTBL | PID), Code(SET), ..] => true,
+        // Starts following the waypoint list.
+        [Code(ODOMETRY), Code(SET), Code(ALL), ..] => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::convert_message;
+
+    #[test]
+    fn sequence_skips_values_containing_msg_end() {
+        assert_eq!(next_sequence(8), 9);
+        assert_eq!(next_sequence(9), 11);
+        assert_eq!(next_sequence(0x09ff), 0x0b00);
+        assert_eq!(next_sequence(u32::MAX), 0);
+
+        let mut safety = Safety::new();
+        safety.heartbeat_interval_ms = 0;
+        for _ in 0..5000 {
+            let message = safety.heartbeat().unwrap();
+            let bytes = convert_message(&message);
+            assert_eq!(
+                bytes.iter().position(|b| *b == MSG_END as u32 as u8),
+                Some(bytes.len() - 1),
+                "{:?}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn heartbeat_waits_for_the_interval() {
+        let mut safety = Safety::new();
+        assert!(safety.heartbeat().is_none());
+
+        safety.heartbeat_interval_ms = 0;
+        assert_eq!(safety.heartbeat(), Some([Code(NONE), U32(1)]));
+        assert_eq!(safety.heartbeat(), Some([Code(NONE), U32(2)]));
+
+        safety.heartbeat_enabled = false;
+        assert!(safety.heartbeat().is_none());
+    }
+
+    #[test]
+    fn link_state_follows_echoes() {
+        let mut safety = Safety::new();
+        assert_eq!(safety.link_state(), LinkState::Waiting);
+
+        safety.ack(1);
+        assert_eq!(safety.link_state(), LinkState::Ok);
+
+        let stale = Instant::now().checked_sub(Duration::from_secs(1)).unwrap();
+        safety.last_ack = Some((1, stale));
+        assert_eq!(safety.link_state(), LinkState::Lost);
+
+        safety.heartbeat_enabled = false;
+        assert_eq!(safety.link_state(), LinkState::Disabled);
+    }
+
+    #[test]
+    fn estop_latches_and_refuses_only_motion() {
+        let mut safety = Safety::new();
+        let drive = [Code(DRIVE_BASE), Code(SET), F32(0.3), U32(0)];
+        let scales = [
+            Code(DRIVE_BASE),
+            Code(SET),
+            Code(ODOMETRY),
+            F32(1.0),
+            F32(1.0),
+        ];
+        let poll = [Code(ENCODER_MOTOR), Code(GET), Code(ANGLE)];
+        assert!(safety.allows(&drive));
+
+        let stops = safety.estop();
+        assert!(safety.estopped);
+        assert!(stops.contains(&vec![Code(ALL), Code(SET), Code(NONE)]));
+
+        assert!(!safety.allows(&drive));
+        assert!(!safety.allows(&[Code(ARM), Code(SET), F32(5.0), F32(5.0)]));
+        assert!(!safety.allows(&[Code(ODOMETRY), Code(SET), Code(ALL), U32(2)]));
+        assert!(safety.allows(&scales));
+        assert!(safety.allows(&poll));
+        assert_eq!(safety.refused, 3);
+
+        safety.release();
+        assert!(!safety.estopped);
+        assert_eq!(safety.refused, 0);
+        assert!(safety.allows(&drive));
+    }
+}
@@ -1,3 +1,40 @@
+//! Serial framing and the shapes of the messages sent to the firmware.
+//!
+//! Outgoing values go on the wire as bare little-endian words with no type
+//! tag, so shapes are distinguished by code sequence and value count only.
+//! Every shape the app sends:
+//!
+//! | Message                                            | Meaning                                           |
+//! |----------------------------------------------------|---------------------------------------------------|
+//! | `NONE U32(seq)`                                    | Heartbeat for the firmware watchdog               |
+//! | `ALL SET NONE`                                     | Emergency stop                                    |
+//! | `ARM SET F32(r) F32(h)`                            | Arm target                                        |
+//! | `CLAW SET F32(deg)`                                | Claw angle                                        |
+//! | `TTBL SET F32(deg)`                                | Turn the turntable by `deg`                       |
+//! | `TTBL SET ANGLE F32(deg)`                          | Turn the turntable to `deg`                       |
+//! | `PID SET <target> F32 × 5`                         | Setpoint, kp, ki, kd, max output                  |
+//! | `DRIVE_BASE SET F32(speed) U32(tape)`              | Base speed and tape following on/off              |
+//! | `DRIVE_BASE SET LEFT F32 RIGHT F32`                | Wheel speeds                                      |
+//! | `DRIVE_BASE SET ODOMETRY F32(wheel) F32(track)`    | Odometry scale factors                            |
+//! | `ODOMETRY SET ANGLE F32(x) F32(y) F32(theta)`      | Reset the pose                                    |
+//! | `ODOMETRY SET NONE`                                | Clear the waypoint list                           |
+//! | `ODOMETRY SET U32(i) F32(x) F32(y)`                | Waypoint `i`                                      |
+//! | `ODOMETRY SET ALL U32(count)`                      | Follow the first `count` waypoints                |
+//! | `LIDAR SET F32(threshold) F32(hyst) U32(width)`    | Peak detection parameters                         |
+//! | `LIDAR SET ALL U32(on)`                            | Start logging, or stop and send the log           |
+//! | `LIDAR SET CONVERTED F32 × 3`                      | Distance correction, constant first               |
+//! | `LIDAR GET RAW` / `LIDAR GET CONVERTED`            | Which distances to stream                         |
+//! | `MAGNETOMETER SET F32 × 6`                         | Offset x, y, soft iron xx, xy, yy, heading offset |
+//! | `TAPE_SENSOR SET RAW F32 …`                        | Per-sensor thresholds, left to right              |
+//! | `TAPE_SENSOR SET PID_KP F32 PID_KI F32 PID_KD F32` | Line-following gains                              |
+//! | `ENCODER_MOTOR GET RAW` / `… GET CONVERTED`        | Units of angle and velocity telemetry             |
+//! | `ENCODER_MOTOR GET ANGLE` / `… GET VELOCITY`       | Request a reading                                 |
+//! | `ENCODER_MOTOR SET CONVERTED F32(counts)`          | Counts per output revolution                      |
+//!
+//! `MessageCode` is generated from the firmware header, so a new command
+//! that would share a shape with an existing one gets an existing code as
+//! a sub-code.
+
 use crate::serial_protocol;
 use serialport::{available_ports, SerialPortType};
 use std::{
@@ -177,7 +214,7 @@ pub fn list_ports() {
     }
 }
 
-fn convert_message(message: &[MsgElem]) -> Vec<u8> {
+pub(crate) fn convert_message(message: &[MsgElem]) -> Vec<u8> {
     let mut converted_message: Vec<u8> = Vec::with_capacity(message.len() * 4 + 2);
     converted_message.push(serial_protocol::MessageCode::MSG_START as u32 as u8);
     for item in message {
@@ -187,7 +224,11 @@ fn convert_message(message: &[MsgElem]) -> Vec<u8> {
     converted_message
 }
 
-pub fn send_message(port: &mut Box<dyn serialport::SerialPort + 'static>, message: &[MsgElem]) {
+/// Returns whether the message was written to the port.
+pub fn send_message(
+    port: &mut Box<dyn serialport::SerialPort + 'static>,
+    message: &[MsgElem],
+) -> bool {
     let converted_message = convert_message(&message);
 
     // for debugging, print message and serial output:
@@ -202,9 +243,13 @@ pub fn send_message(port: &mut Box<dyn serialport::SerialPort + 'static>, messag
     match port.write(&converted_message[..]) {
         Ok(_) => {
             // println!("Sent message successfully.")
+            true
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::TimedOut => false,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            false
         }
-        Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
-        Err(e) => eprintln!("{:?}", e),
     }
 }
 
@@ -253,12 +298,75 @@ fn parse_to_message(buffer: &[u8]) -> Option<Vec<MsgElem>> {
     Some(message)
 }
 
+/// The values of a run of `F32` elements, or `None` if any element isn't one.
+pub fn f32_values(elems: &[MsgElem]) -> Option<Vec<f32>> {
+    elems
+        .iter()
+        .map(|e| {
+            if let MsgElem::F32(x) = e {
+                Some(*x)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Check that `msg1` has the shape of the template `msg2`: codes must match
+/// exactly, numbers only need to be of the same type.
 pub fn compare_messages(msg1: &[MsgElem], msg2: &[MsgElem]) -> bool {
     if msg1.len() != msg2.len() {
         return false;
     }
 
-    msg1.iter()
-        .zip(msg2.iter())
-        .all(|(x, y)| discriminant(x) == discriminant(y))
+    msg1.iter().zip(msg2.iter()).all(|(x, y)| match (x, y) {
+        (MsgElem::Code(a), MsgElem::Code(b)) => a == b,
+        _ => discriminant(x) == discriminant(y),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MsgElem::*;
+    use super::*;
+    use crate::serial_protocol::MessageCode::*;
+
+    const TEMPLATE: [MsgElem; 3] = [Code(TTBL), Code(ANGLE), F32(0.0)];
+
+    #[test]
+    fn matches_same_codes_and_number_types() {
+        assert!(compare_messages(
+            &[Code(TTBL), Code(ANGLE), F32(42.0)],
+            &TEMPLATE
+        ));
+    }
+
+    #[test]
+    fn rejects_different_codes() {
+        assert!(!compare_messages(
+            &[Code(CLAW), Code(ANGLE), F32(42.0)],
+            &TEMPLATE
+        ));
+        assert!(!compare_messages(
+            &[Code(TTBL), Code(VELOCITY), F32(42.0)],
+            &TEMPLATE
+        ));
+    }
+
+    #[test]
+    fn rejects_different_number_types() {
+        assert!(!compare_messages(
+            &[Code(TTBL), Code(ANGLE), U32(42)],
+            &TEMPLATE
+        ));
+        assert!(!compare_messages(
+            &[Code(TTBL), Code(ANGLE), Code(NONE)],
+            &TEMPLATE
+        ));
+    }
+
+    #[test]
+    fn rejects_different_lengths() {
+        assert!(!compare_messages(&[Code(TTBL), Code(ANGLE)], &TEMPLATE));
+    }
 }
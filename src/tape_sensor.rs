@@ -0,0 +1,108 @@
+//! Tape sensor array telemetry and line position for line-following tuning.
+//!
+//! `TAPE_SENSOR RAW ...` and `TAPE_SENSOR CONVERTED ...` each carry one value
+//! per sensor, left to right. The line position is the centroid of how far
+//! each raw reading is above its threshold.
+
+use crate::ring_buffer::RingBuffer;
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+/// Threshold given to sensors before one has been set, in raw units.
+const DEFAULT_THRESHOLD: f32 = 500.0;
+
+pub struct TapeSensor {
+    pub raw: Vec<f32>,
+    pub converted: Vec<f32>,
+    /// Per-sensor thresholds in raw units.
+    pub thresholds: Vec<f32>,
+    /// Distance between neighbouring sensors, metres.
+    pub spacing: f32,
+
+    samples: usize,
+    /// Sample number and line position, metres left of centre.
+    pub position_history: RingBuffer<[f64; 2]>,
+
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+impl TapeSensor {
+    pub fn new() -> Self {
+        Self {
+            raw: Vec::new(),
+            converted: Vec::new(),
+            thresholds: Vec::new(),
+            spacing: 0.01,
+            samples: 0,
+            position_history: RingBuffer::new(512),
+            kp: 0.0,
+            ki: 0.0,
+            kd: 0.0,
+        }
+    }
+
+    pub fn record_raw(&mut self, values: Vec<f32>) {
+        if self.thresholds.len() != values.len() {
+            let threshold = self
+                .thresholds
+                .first()
+                .copied()
+                .unwrap_or(DEFAULT_THRESHOLD);
+            self.thresholds = vec![threshold; values.len()];
+        }
+        self.raw = values;
+
+        self.samples += 1;
+        if let Some(position) = self.line_position() {
+            self.position_history
+                .push([self.samples as f64, position as f64]);
+        }
+    }
+
+    pub fn record_converted(&mut self, values: Vec<f32>) {
+        self.converted = values;
+    }
+
+    /// Lateral offset of sensor `i` from the centre of the array, positive to
+    /// the left.
+    pub fn sensor_offset(&self, i: usize) -> f32 {
+        (0.5 * (self.raw.len() as f32 - 1.0) - i as f32) * self.spacing
+    }
+
+    /// Line position, or `None` if no sensor is over the tape.
+    pub fn line_position(&self) -> Option<f32> {
+        let (sum, weight) = self
+            .raw
+            .iter()
+            .zip(&self.thresholds)
+            .enumerate()
+            .map(|(i, (value, threshold))| (i, (value - threshold).max(0.0)))
+            .fold((0.0, 0.0), |(sum, weight), (i, w)| {
+                (sum + w * self.sensor_offset(i), weight + w)
+            });
+        (weight > 0.0).then(|| sum / weight)
+    }
+
+    /// `TAPE_SENSOR SET RAW <threshold> ...`
+    pub fn thresholds_message(&self) -> Vec<MsgElem> {
+        let mut message = vec![Code(TAPE_SENSOR), Code(SET), Code(RAW)];
+        message.extend(self.thresholds.iter().map(|t| F32(*t)));
+        message
+    }
+
+    /// `TAPE_SENSOR SET PID_KP <kp> PID_KI <ki> PID_KD <kd>`
+    pub fn gains_message(&self) -> [MsgElem; 8] {
+        [
+            Code(TAPE_SENSOR),
+            Code(SET),
+            Code(PID_KP),
+            F32(self.kp),
+            Code(PID_KI),
+            F32(self.ki),
+            Code(PID_KD),
+            F32(self.kd),
+        ]
+    }
+}
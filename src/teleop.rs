@@ -0,0 +1,314 @@
+//! Keyboard and gamepad teleoperation of the drive base and arm.
+//!
+//! Inputs are read every frame and integrated into pending arm and turntable
+//! moves. Commands only go out once per `send_interval_ms` so holding a key
+//! doesn't flood the serial link.
+
+use eframe::egui::{self, Key};
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use std::time::Instant;
+
+pub struct KeyBindings {
+    pub forward: Key,
+    pub backward: Key,
+    pub turn_left: Key,
+    pub turn_right: Key,
+    pub arm_out: Key,
+    pub arm_in: Key,
+    pub arm_up: Key,
+    pub arm_down: Key,
+    pub turntable_left: Key,
+    pub turntable_right: Key,
+    pub claw: Key,
+}
+
+impl KeyBindings {
+    pub fn new() -> Self {
+        Self {
+            forward: Key::W,
+            backward: Key::S,
+            turn_left: Key::A,
+            turn_right: Key::D,
+            arm_out: Key::ArrowUp,
+            arm_in: Key::ArrowDown,
+            arm_up: Key::PageUp,
+            arm_down: Key::PageDown,
+            turntable_left: Key::Q,
+            turntable_right: Key::E,
+            claw: Key::Space,
+        }
+    }
+
+    pub fn entries(&mut self) -> [(&'static str, &mut Key); 11] {
+        [
+            ("Forward", &mut self.forward),
+            ("Backward", &mut self.backward),
+            ("Turn left", &mut self.turn_left),
+            ("Turn right", &mut self.turn_right),
+            ("Arm out", &mut self.arm_out),
+            ("Arm in", &mut self.arm_in),
+            ("Arm up", &mut self.arm_up),
+            ("Arm down", &mut self.arm_down),
+            ("Turntable left", &mut self.turntable_left),
+            ("Turntable right", &mut self.turntable_right),
+            ("Claw", &mut self.claw),
+        ]
+    }
+}
+
+pub const GAMEPAD_AXES: [Axis; 6] = [
+    Axis::LeftStickX,
+    Axis::LeftStickY,
+    Axis::RightStickX,
+    Axis::RightStickY,
+    Axis::LeftZ,
+    Axis::RightZ,
+];
+
+pub const GAMEPAD_BUTTONS: [Button; 12] = [
+    Button::South,
+    Button::East,
+    Button::North,
+    Button::West,
+    Button::LeftTrigger,
+    Button::RightTrigger,
+    Button::LeftTrigger2,
+    Button::RightTrigger2,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+pub struct GamepadMapping {
+    pub drive: Axis,
+    pub turn: Axis,
+    pub arm_r: Axis,
+    pub turntable: Axis,
+    pub arm_up: Button,
+    pub arm_down: Button,
+    pub claw: Button,
+}
+
+impl GamepadMapping {
+    pub fn new() -> Self {
+        Self {
+            drive: Axis::LeftStickY,
+            turn: Axis::LeftStickX,
+            arm_r: Axis::RightStickY,
+            turntable: Axis::RightStickX,
+            arm_up: Button::DPadUp,
+            arm_down: Button::DPadDown,
+            claw: Button::South,
+        }
+    }
+
+    pub fn axes(&mut self) -> [(&'static str, &mut Axis); 4] {
+        [
+            ("Drive", &mut self.drive),
+            ("Turn", &mut self.turn),
+            ("Arm r", &mut self.arm_r),
+            ("Turntable", &mut self.turntable),
+        ]
+    }
+
+    pub fn buttons(&mut self) -> [(&'static str, &mut Button); 3] {
+        [
+            ("Arm up", &mut self.arm_up),
+            ("Arm down", &mut self.arm_down),
+            ("Claw", &mut self.claw),
+        ]
+    }
+}
+
+/// What to send this tick. Arm and turntable moves are relative.
+pub struct TeleopCommand {
+    /// Left and right wheel speeds.
+    pub drive: Option<[f32; 2]>,
+    pub arm: [f32; 2],
+    /// Degrees, sent with the same relative `TTBL SET` as the arm view.
+    pub turntable: f32,
+    pub claw_toggle: bool,
+}
+
+pub struct Teleop {
+    pub enabled: bool,
+    pub keys: KeyBindings,
+    pub gamepad: GamepadMapping,
+
+    /// Stick deflection below this is ignored.
+    pub dead_zone: f32,
+    pub drive_speed: f32,
+    pub turn_speed: f32,
+    /// Arm plot units per second at full deflection.
+    pub arm_rate: f32,
+    /// Degrees per second at full deflection.
+    pub turntable_rate: f32,
+    pub send_interval_ms: u32,
+
+    gilrs: Option<Gilrs>,
+    pub gamepad_status: String,
+    active_gamepad: Option<GamepadId>,
+
+    last_tick: Instant,
+    last_send: Instant,
+    pending_arm: [f32; 2],
+    pending_turntable: f32,
+    pending_claw: bool,
+    // Whether the last drive command was non-zero, so a stop gets sent once.
+    driving: bool,
+}
+
+impl Teleop {
+    pub fn new() -> Self {
+        let (gilrs, gamepad_status) = match Gilrs::new() {
+            Ok(gilrs) => (Some(gilrs), String::from("No gamepad connected.")),
+            Err(e) => (None, format!("Gamepad support unavailable: {}", e)),
+        };
+
+        Self {
+            enabled: false,
+            keys: KeyBindings::new(),
+            gamepad: GamepadMapping::new(),
+            dead_zone: 0.15,
+            drive_speed: 0.3,
+            turn_speed: 0.2,
+            arm_rate: 6.0,
+            turntable_rate: 90.0,
+            send_interval_ms: 100,
+            gilrs,
+            gamepad_status,
+            active_gamepad: None,
+            last_tick: Instant::now(),
+            last_send: Instant::now(),
+            pending_arm: [0.0, 0.0],
+            pending_turntable: 0.0,
+            pending_claw: false,
+            driving: false,
+        }
+    }
+
+    /// Read inputs and return a command once the send interval has passed.
+    pub fn update(&mut self, ctx: &egui::Context) -> Option<TeleopCommand> {
+        let dt = self.last_tick.elapsed().as_secs_f32();
+        self.last_tick = Instant::now();
+
+        // [drive, turn, arm r, arm h, turntable]
+        let mut input = [0.0f32; 5];
+
+        if let Some(gilrs) = self.gilrs.as_mut() {
+            while let Some(event) = gilrs.next_event() {
+                match event.event {
+                    EventType::Connected => {
+                        self.active_gamepad = Some(event.id);
+                    }
+                    EventType::Disconnected if self.active_gamepad == Some(event.id) => {
+                        self.active_gamepad = None;
+                    }
+                    EventType::ButtonPressed(button, _) => {
+                        self.active_gamepad = Some(event.id);
+                        if self.enabled && button == self.gamepad.claw {
+                            self.pending_claw = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if self.active_gamepad.is_none() {
+                self.active_gamepad = gilrs.gamepads().next().map(|(id, _)| id);
+            }
+
+            match self
+                .active_gamepad
+                .and_then(|id| gilrs.connected_gamepad(id))
+            {
+                Some(gamepad) => {
+                    self.gamepad_status = format!("Using {}.", gamepad.name());
+                    let axis = |a: Axis| dead_zone(gamepad.value(a), self.dead_zone);
+                    let button = |b: Button| gamepad.is_pressed(b) as i32 as f32;
+                    input[0] += axis(self.gamepad.drive);
+                    // Stick x is positive to the right, turning left is positive.
+                    input[1] -= axis(self.gamepad.turn);
+                    input[2] += axis(self.gamepad.arm_r);
+                    input[3] += button(self.gamepad.arm_up) - button(self.gamepad.arm_down);
+                    input[4] -= axis(self.gamepad.turntable);
+                }
+                None => self.gamepad_status = String::from("No gamepad connected."),
+            }
+        }
+
+        if !ctx.wants_keyboard_input() {
+            let keys = &self.keys;
+            ctx.input(|i| {
+                let axis = |pos: Key, neg: Key| {
+                    i.key_down(pos) as i32 as f32 - i.key_down(neg) as i32 as f32
+                };
+                input[0] += axis(keys.forward, keys.backward);
+                input[1] += axis(keys.turn_left, keys.turn_right);
+                input[2] += axis(keys.arm_out, keys.arm_in);
+                input[3] += axis(keys.arm_up, keys.arm_down);
+                input[4] += axis(keys.turntable_left, keys.turntable_right);
+                if self.enabled && i.key_pressed(keys.claw) {
+                    self.pending_claw = true;
+                }
+            });
+        }
+
+        if !self.enabled {
+            self.pending_arm = [0.0, 0.0];
+            self.pending_turntable = 0.0;
+            if self.driving {
+                self.driving = false;
+                return Some(TeleopCommand {
+                    drive: Some([0.0, 0.0]),
+                    arm: [0.0, 0.0],
+                    turntable: 0.0,
+                    claw_toggle: false,
+                });
+            }
+            return None;
+        }
+
+        let input = input.map(|v| v.clamp(-1.0, 1.0));
+        self.pending_arm[0] += input[2] * self.arm_rate * dt;
+        self.pending_arm[1] += input[3] * self.arm_rate * dt;
+        self.pending_turntable += input[4] * self.turntable_rate * dt;
+
+        if self.last_send.elapsed().as_millis() < self.send_interval_ms as u128 {
+            return None;
+        }
+        self.last_send = Instant::now();
+
+        let moving = input[0] != 0.0 || input[1] != 0.0;
+        let drive = if moving || self.driving {
+            let forward = input[0] * self.drive_speed;
+            let turn = input[1] * self.turn_speed;
+            Some([forward - turn, forward + turn])
+        } else {
+            None
+        };
+        self.driving = moving;
+
+        let command = TeleopCommand {
+            drive,
+            arm: self.pending_arm,
+            turntable: self.pending_turntable,
+            claw_toggle: self.pending_claw,
+        };
+        self.pending_arm = [0.0, 0.0];
+        self.pending_turntable = 0.0;
+        self.pending_claw = false;
+
+        Some(command)
+    }
+}
+
+/// Zero inside the dead zone, rescaled so the output still reaches 1.
+fn dead_zone(value: f32, zone: f32) -> f32 {
+    if value.abs() <= zone || zone >= 1.0 {
+        0.0
+    } else {
+        value.signum() * (value.abs() - zone) / (1.0 - zone)
+    }
+}
@@ -0,0 +1,110 @@
+use crate::ring_buffer::RingBuffer;
+use crate::serial::MsgElem::{self, *};
+use crate::serial_protocol::MessageCode::*;
+
+/// Waypoint list built on the odometry plot, in raw odometry coordinates.
+pub struct WaypointPlan {
+    pub points: Vec<[f64; 2]>,
+    /// Signed distance from the planned path, left of the path is positive.
+    pub cross_track_history: RingBuffer<f64>,
+}
+
+impl WaypointPlan {
+    pub fn new() -> Self {
+        Self {
+            points: Vec::new(),
+            cross_track_history: RingBuffer::new(512),
+        }
+    }
+
+    pub fn cross_track_error(&self, p: [f64; 2]) -> Option<f64> {
+        cross_track_error(&self.points, p)
+    }
+
+    pub fn record(&mut self, p: [f64; 2]) {
+        if let Some(error) = self.cross_track_error(p) {
+            self.cross_track_history.push(error);
+        }
+    }
+
+    /// Messages that replace the robot's waypoint list and start following
+    /// it: clear, one `ODOMETRY SET <index> <x> <y>` per point, then commit
+    /// with the point count.
+    pub fn to_messages(&self) -> Vec<Vec<MsgElem>> {
+        let mut messages = vec![vec![Code(ODOMETRY), Code(SET), Code(NONE)]];
+        for (i, p) in self.points.iter().enumerate() {
+            messages.push(vec![
+                Code(ODOMETRY),
+                Code(SET),
+                U32(i as u32),
+                F32(p[0] as f32),
+                F32(p[1] as f32),
+            ]);
+        }
+        messages.push(vec![
+            Code(ODOMETRY),
+            Code(SET),
+            Code(ALL),
+            U32(self.points.len() as u32),
+        ]);
+        messages
+    }
+}
+
+/// Signed distance from `p` to the closest segment of `path`.
+pub fn cross_track_error(path: &[[f64; 2]], p: [f64; 2]) -> Option<f64> {
+    let mut best: Option<f64> = None;
+
+    for segment in path.windows(2) {
+        let [a, b] = [segment[0], segment[1]];
+        let d = [b[0] - a[0], b[1] - a[1]];
+        let length_sq = d[0] * d[0] + d[1] * d[1];
+        let t = if length_sq > 0.0 {
+            (((p[0] - a[0]) * d[0] + (p[1] - a[1]) * d[1]) / length_sq).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let closest = [a[0] + t * d[0], a[1] + t * d[1]];
+        let distance = (p[0] - closest[0]).hypot(p[1] - closest[1]);
+        let side = d[0] * (p[1] - a[1]) - d[1] * (p[0] - a[0]);
+        let error = if side < 0.0 { -distance } else { distance };
+
+        if best.is_none_or(|x| distance < x.abs()) {
+            best = Some(error);
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_clear_send_each_point_then_commit() {
+        let mut plan = WaypointPlan::new();
+        plan.points = vec![[1.0, 2.0], [3.0, -4.0]];
+
+        assert_eq!(
+            plan.to_messages(),
+            vec![
+                vec![Code(ODOMETRY), Code(SET), Code(NONE)],
+                vec![Code(ODOMETRY), Code(SET), U32(0), F32(1.0), F32(2.0)],
+                vec![Code(ODOMETRY), Code(SET), U32(1), F32(3.0), F32(-4.0)],
+                vec![Code(ODOMETRY), Code(SET), Code(ALL), U32(2)],
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_plan_still_clears_and_commits() {
+        assert_eq!(
+            WaypointPlan::new().to_messages(),
+            vec![
+                vec![Code(ODOMETRY), Code(SET), Code(NONE)],
+                vec![Code(ODOMETRY), Code(SET), Code(ALL), U32(0)],
+            ]
+        );
+    }
+}